@@ -0,0 +1,500 @@
+use crate::emulator::{RAM_SIZE, START_ADDR};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// How deep included files can include other files
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// How deep constants can be defined in terms of other constants
+const MAX_CONSTANT_DEPTH: usize = 16;
+
+/// What went wrong, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembles source code into a ROM, loaded at START_ADDR.
+/// Included files are looked up from the current directory.
+///
+/// The syntax is the disassembler's, so its listings assemble back:
+///
+/// ```text
+/// ; Comments start with a semicolon
+/// speed equ 2             ; Constants
+/// main:                   ; Labels
+///     LD V0, speed + 1
+///     LD I, sprite
+///     DRW V0, V0, 2
+///     JP main
+/// sprite: db 0xF0, 0b10010000
+///     dw 0x1234           ; Words are big endian
+///     include "other.asm"
+/// ```
+///
+/// Numbers are decimal, 0x hex or 0b binary, and can be added to or
+/// subtracted from labels and constants. `LD I, LONG addr` is the
+/// XO-CHIP F000 NNNN instruction.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines: Vec<SourceLine> = Vec::new();
+    read_source(source, "<input>", Path::new(""), 0, &mut lines)?;
+    Assembler::new(&lines)?.encode()
+}
+
+/// Assembles the file at path, looking up included files from its directory
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    let source: String = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        message: err.to_string(),
+    })?;
+
+    let mut lines: Vec<SourceLine> = Vec::new();
+    let dir: &Path = path.parent().unwrap_or(Path::new(""));
+    read_source(&source, &path.display().to_string(), dir, 0, &mut lines)?;
+    Assembler::new(&lines)?.encode()
+}
+
+/// A line of code, with the comment stripped off
+struct SourceLine {
+    file: String,
+    line: usize,
+    text: String,
+}
+
+impl SourceLine {
+
+    fn error(&self, message: String) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            message,
+        }
+    }
+}
+
+/// Reads the lines of the source, replacing includes with the lines they include
+fn read_source(source: &str, file: &str, dir: &Path, depth: usize, out: &mut Vec<SourceLine>) -> Result<(), AsmError> {
+    for (idx, line) in source.lines().enumerate() {
+        let text: &str = line.split(';').next().unwrap_or("").trim();
+        let source_line = SourceLine {
+            file: file.to_string(),
+            line: idx + 1,
+            text: text.to_string(),
+        };
+
+        let include: Option<&str> = text
+            .split_once(char::is_whitespace)
+            .filter(|(keyword, _)| keyword.eq_ignore_ascii_case("include"))
+            .map(|(_, path)| path.trim().trim_matches('"'));
+
+        match include {
+            None if !text.is_empty() => out.push(source_line),
+            None => {}
+            Some(_) if depth >= MAX_INCLUDE_DEPTH => {
+                return Err(source_line.error("includes are nested too deep".to_string()));
+            }
+            Some(path) => {
+                let path = dir.join(path);
+                let included: String = fs::read_to_string(&path)
+                    .map_err(|err| source_line.error(format!("can't include {}: {}", path.display(), err)))?;
+                let included_dir: &Path = path.parent().unwrap_or(Path::new(""));
+                read_source(&included, &path.display().to_string(), included_dir, depth + 1, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+enum Symbol {
+    Label(u16),
+    Constant(String), // Evaluated when used, as it may refer to later labels
+}
+
+/// An instruction or data directive, placed at addr
+struct Statement<'a> {
+    source: &'a SourceLine,
+    addr: u16,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+#[derive(Clone, Copy)]
+enum Operand<'a> {
+    Reg(u8),
+    /// I, DT, ST, K, F, HF, B, R or [I]
+    Named(&'static str),
+    /// LONG addr
+    Long(&'a str),
+    Value(&'a str),
+}
+
+struct Assembler<'a> {
+    symbols: HashMap<&'a str, Symbol>,
+    statements: Vec<Statement<'a>>,
+}
+
+impl<'a> Assembler<'a> {
+
+    /// First pass: places every statement and label
+    fn new(lines: &'a [SourceLine]) -> Result<Self, AsmError> {
+        let mut assembler = Self {
+            symbols: HashMap::new(),
+            statements: Vec::new(),
+        };
+        let mut addr: usize = START_ADDR as usize;
+
+        for source in lines {
+            let mut text: &str = &source.text;
+
+            if let Some((label, rest)) = text.split_once(':') {
+                assembler.define(source, label.trim(), Symbol::Label(addr as u16))?;
+                text = rest.trim();
+            }
+            if text.is_empty() {
+                continue;
+            }
+
+            let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            let rest: &str = rest.trim();
+
+            if let Some((keyword, value)) = rest.split_once(char::is_whitespace) {
+                if keyword.eq_ignore_ascii_case("equ") {
+                    assembler.define(source, mnemonic, Symbol::Constant(value.trim().to_string()))?;
+                    continue;
+                }
+            }
+
+            let operands: Vec<&str> = if rest.is_empty() { Vec::new() } else { rest.split(',').map(str::trim).collect() };
+            let mnemonic: String = mnemonic.to_ascii_uppercase();
+            let size: usize = match mnemonic.as_str() {
+                "DB" => operands.len(),
+                "DW" => operands.len() * 2,
+                "LD" if operands.len() == 2 && matches!(operand(operands[1]), Operand::Long(_)) => 4,
+                _ => 2,
+            };
+
+            assembler.statements.push(Statement { source, addr: addr as u16, mnemonic, operands });
+            addr += size;
+            if addr > RAM_SIZE {
+                return Err(source.error("the program doesn't fit in RAM".to_string()));
+            }
+        }
+
+        Ok(assembler)
+    }
+
+    fn define(&mut self, source: &SourceLine, name: &'a str, symbol: Symbol) -> Result<(), AsmError> {
+        let valid: bool = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(source.error(format!("'{}' isn't a valid name", name)));
+        }
+        if self.symbols.insert(name, symbol).is_some() {
+            return Err(source.error(format!("'{}' is already defined", name)));
+        }
+        Ok(())
+    }
+
+    /// Second pass: encodes every statement, now that all labels are known
+    fn encode(&self) -> Result<Vec<u8>, AsmError> {
+        let mut rom: Vec<u8> = Vec::new();
+
+        for statement in &self.statements {
+            let bytes: Vec<u8> = self.encode_statement(statement).map_err(|message| statement.source.error(message))?;
+            debug_assert_eq!(START_ADDR as usize + rom.len(), statement.addr as usize);
+            rom.extend(bytes);
+        }
+
+        Ok(rom)
+    }
+
+    fn encode_statement(&self, statement: &Statement) -> Result<Vec<u8>, String> {
+        match statement.mnemonic.as_str() {
+            "DB" => return statement.operands.iter().map(|value| self.byte(value)).collect(),
+            "DW" => {
+                let words: Vec<u16> = statement.operands.iter().map(|value| self.word(value)).collect::<Result<_, _>>()?;
+                return Ok(words.iter().flat_map(|word| word.to_be_bytes()).collect());
+            }
+            _ => {}
+        }
+
+        let operands: Vec<Operand> = statement.operands.iter().map(|op| operand(op)).collect();
+        let xy = |x: u8, y: u8| ((x as u16) << 8) | ((y as u16) << 4);
+        let xnn = |x: u8, nn: &str| -> Result<u16, String> { Ok(((x as u16) << 8) | self.byte(nn)? as u16) };
+
+        use Operand::*;
+        let op: u16 = match (statement.mnemonic.as_str(), operands.as_slice()) {
+            ("NOP", []) => 0x0000,
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("SCD", [Value(n)]) => 0x00C0 | self.nibble(n)?,
+            ("SCU", [Value(n)]) => 0x00D0 | self.nibble(n)?,
+            ("SCR", []) => 0x00FB,
+            ("SCL", []) => 0x00FC,
+            ("EXIT", []) => 0x00FD,
+            ("LOW", []) => 0x00FE,
+            ("HIGH", []) => 0x00FF,
+            ("SYS", [Value(nnn)]) => self.addr(nnn)?,
+            ("JP", [Value(nnn)]) => 0x1000 | self.addr(nnn)?,
+            ("JP", [Reg(0), Value(nnn)]) => 0xB000 | self.addr(nnn)?,
+            ("CALL", [Value(nnn)]) => 0x2000 | self.addr(nnn)?,
+            ("SE", [Reg(x), Value(nn)]) => 0x3000 | xnn(*x, nn)?,
+            ("SNE", [Reg(x), Value(nn)]) => 0x4000 | xnn(*x, nn)?,
+            ("SE", [Reg(x), Reg(y)]) => 0x5000 | xy(*x, *y),
+            ("SAVE", [Reg(x), Reg(y)]) => 0x5002 | xy(*x, *y),
+            ("LOAD", [Reg(x), Reg(y)]) => 0x5003 | xy(*x, *y),
+            ("LD", [Reg(x), Value(nn)]) => 0x6000 | xnn(*x, nn)?,
+            ("ADD", [Reg(x), Value(nn)]) => 0x7000 | xnn(*x, nn)?,
+            ("LD", [Reg(x), Reg(y)]) => 0x8000 | xy(*x, *y),
+            ("OR", [Reg(x), Reg(y)]) => 0x8001 | xy(*x, *y),
+            ("AND", [Reg(x), Reg(y)]) => 0x8002 | xy(*x, *y),
+            ("XOR", [Reg(x), Reg(y)]) => 0x8003 | xy(*x, *y),
+            ("ADD", [Reg(x), Reg(y)]) => 0x8004 | xy(*x, *y),
+            ("SUB", [Reg(x), Reg(y)]) => 0x8005 | xy(*x, *y),
+            ("SHR", [Reg(x)]) => 0x8006 | xy(*x, *x),
+            ("SHR", [Reg(x), Reg(y)]) => 0x8006 | xy(*x, *y),
+            ("SUBN", [Reg(x), Reg(y)]) => 0x8007 | xy(*x, *y),
+            ("SHL", [Reg(x)]) => 0x800E | xy(*x, *x),
+            ("SHL", [Reg(x), Reg(y)]) => 0x800E | xy(*x, *y),
+            ("SNE", [Reg(x), Reg(y)]) => 0x9000 | xy(*x, *y),
+            ("LD", [Named("I"), Value(nnn)]) => 0xA000 | self.addr(nnn)?,
+            ("LD", [Named("I"), Long(nnnn)]) => {
+                let [high, low] = self.word(nnnn)?.to_be_bytes();
+                return Ok(vec![0xF0, 0x00, high, low]);
+            }
+            ("RND", [Reg(x), Value(nn)]) => 0xC000 | xnn(*x, nn)?,
+            ("DRW", [Reg(x), Reg(y), Value(n)]) => 0xD000 | xy(*x, *y) | self.nibble(n)?,
+            ("SKP", [Reg(x)]) => 0xE09E | xy(*x, 0),
+            ("SKNP", [Reg(x)]) => 0xE0A1 | xy(*x, 0),
+            ("AUDIO", []) => 0xF002,
+            ("PLANE", [Value(n)]) => 0xF001 | (self.nibble(n)? << 8),
+            ("LD", [Reg(x), Named("DT")]) => 0xF007 | xy(*x, 0),
+            ("LD", [Reg(x), Named("K")]) => 0xF00A | xy(*x, 0),
+            ("LD", [Named("DT"), Reg(x)]) => 0xF015 | xy(*x, 0),
+            ("LD", [Named("ST"), Reg(x)]) => 0xF018 | xy(*x, 0),
+            ("ADD", [Named("I"), Reg(x)]) => 0xF01E | xy(*x, 0),
+            ("LD", [Named("F"), Reg(x)]) => 0xF029 | xy(*x, 0),
+            ("LD", [Named("HF"), Reg(x)]) => 0xF030 | xy(*x, 0),
+            ("LD", [Named("B"), Reg(x)]) => 0xF033 | xy(*x, 0),
+            ("PITCH", [Reg(x)]) => 0xF03A | xy(*x, 0),
+            ("LD", [Named("[I]"), Reg(x)]) => 0xF055 | xy(*x, 0),
+            ("LD", [Reg(x), Named("[I]")]) => 0xF065 | xy(*x, 0),
+            ("LD", [Named("R"), Reg(x)]) => 0xF075 | xy(*x, 0),
+            ("LD", [Reg(x), Named("R")]) => 0xF085 | xy(*x, 0),
+            (mnemonic, _) => {
+                let text: String = format!("{} {}", mnemonic, statement.operands.join(", "));
+                return Err(format!("'{}' isn't a valid instruction", text.trim()));
+            }
+        };
+
+        Ok(op.to_be_bytes().to_vec())
+    }
+
+    fn addr(&self, expr: &str) -> Result<u16, String> {
+        self.ranged(expr, 0, 0xFFF, "a 12 bit address")
+    }
+
+    fn nibble(&self, expr: &str) -> Result<u16, String> {
+        self.ranged(expr, 0, 0xF, "4 bits")
+    }
+
+    /// Negative bytes are stored as two's complement
+    fn byte(&self, expr: &str) -> Result<u8, String> {
+        Ok(self.ranged(expr, -0x80, 0xFF, "a byte")? as u8)
+    }
+
+    fn word(&self, expr: &str) -> Result<u16, String> {
+        self.ranged(expr, 0, 0xFFFF, "16 bits")
+    }
+
+    fn ranged(&self, expr: &str, min: i64, max: i64, what: &str) -> Result<u16, String> {
+        let value: i64 = self.eval(expr, 0)?;
+        if value < min || value > max {
+            return Err(format!("{} doesn't fit in {}", value, what));
+        }
+        Ok(value as u16)
+    }
+
+    /// Sums up numbers, labels and constants
+    fn eval(&self, expr: &str, depth: usize) -> Result<i64, String> {
+        let mut total: i64 = 0;
+        let mut sign: i64 = 1;
+        let mut start: usize = 0;
+
+        for (idx, c) in expr.char_indices().chain(std::iter::once((expr.len(), '+'))) {
+            if c != '+' && c != '-' {
+                continue;
+            }
+            let term: &str = expr[start..idx].trim();
+            if !term.is_empty() {
+                total = sign
+                    .checked_mul(self.term(term, depth)?)
+                    .and_then(|value| total.checked_add(value))
+                    .ok_or_else(|| format!("'{}' overflows", expr))?;
+            } else if start != 0 || idx == expr.len() {
+                return Err(format!("missing a value in '{}'", expr));
+            }
+            sign = if c == '-' { -1 } else { 1 };
+            start = idx + 1;
+        }
+
+        Ok(total)
+    }
+
+    fn term(&self, term: &str, depth: usize) -> Result<i64, String> {
+        if term.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_number(term).ok_or_else(|| format!("'{}' isn't a valid number", term));
+        }
+
+        match self.symbols.get(term) {
+            Some(Symbol::Label(addr)) => Ok(*addr as i64),
+            Some(Symbol::Constant(_)) if depth >= MAX_CONSTANT_DEPTH => {
+                Err(format!("'{}' is defined in terms of itself", term))
+            }
+            Some(Symbol::Constant(expr)) => self.eval(expr, depth + 1),
+            None => Err(format!("unknown label or constant '{}'", term)),
+        }
+    }
+}
+
+fn operand(text: &str) -> Operand<'_> {
+    let upper: String = text.to_ascii_uppercase();
+
+    if let Some(reg) = upper.strip_prefix('V').filter(|reg| reg.len() == 1) {
+        if let Ok(reg) = u8::from_str_radix(reg, 16) {
+            return Operand::Reg(reg);
+        }
+    }
+    for name in ["I", "DT", "ST", "K", "F", "HF", "B", "R", "[I]"] {
+        if upper == name {
+            return Operand::Named(name);
+        }
+    }
+    match upper.strip_prefix("LONG") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => Operand::Long(text[4..].trim()),
+        _ => Operand::Value(text),
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+    use crate::emulator::Emulator;
+
+    const PROGRAM: &str = "
+        ; Draws a sprite, forever
+        x equ 0x10
+        y equ x + 4
+        start:
+            LD V0, x
+            LD V1, y    ; Constants can use constants
+            CALL draw
+        loop: JP loop
+        draw:
+            LD I, sprite
+            DRW V0, V1, 2
+            SHR V2
+            LD I, LONG 0x1234
+            RET
+        sprite: db 0xF0, 0b10010000, -1
+            dw start
+    ";
+
+    #[test]
+    fn assembles_labels_constants_and_data() {
+        let rom = assemble(PROGRAM).unwrap();
+        assert_eq!(rom, [
+            0x60, 0x10, 0x61, 0x14, 0x22, 0x08, 0x12, 0x06,
+            0xA2, 0x14, 0xD0, 0x12, 0x82, 0x26, 0xF0, 0x00,
+            0x12, 0x34, 0x00, 0xEE, 0xF0, 0x90, 0xFF, 0x02,
+            0x00,
+        ]);
+
+        let mut emulator = Emulator::default();
+        emulator.load(&rom).unwrap();
+        for _ in 0..4 {
+            emulator.tick().unwrap();
+        }
+        assert_eq!((emulator.v_reg()[1], emulator.i_reg()), (0x14, 0x214));
+    }
+
+    fn listing(rom: &[u8]) -> String {
+        disassemble(rom, START_ADDR)
+            .iter()
+            .map(|line| match &line.label {
+                Some(label) => format!("{}: {}\n", label, line.text),
+                None => format!("{}\n", line.text),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn disassembly_assembles_back() {
+        let rom = assemble(PROGRAM).unwrap();
+        assert_eq!(assemble(&listing(&rom)), Ok(rom));
+    }
+
+    #[test]
+    fn disassembly_of_targets_without_a_line_assembles_back() {
+        // LD I, 0x050 (the font), DRW V0, V0, 5, LD I, 0x300 (past the end),
+        // SE V0, 0, JP 0x209 (inside itself), JP 0x20A
+        let rom: [u8; 12] = [0xA0, 0x50, 0xD0, 0x05, 0xA3, 0x00, 0x30, 0x00, 0x12, 0x09, 0x12, 0x0A];
+        let listing: String = listing(&rom);
+
+        assert!(listing.contains("LD I, 0x050"), "{}", listing);
+        assert!(listing.contains("LD I, 0x300"), "{}", listing);
+        assert!(listing.contains("JP 0x209"), "{}", listing);
+        assert_eq!(assemble(&listing), Ok(rom.to_vec()));
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let error = |source: &str| assemble(source).unwrap_err().to_string();
+
+        assert_eq!(error("CLS\n\nJP nowhere"), "<input>:3: unknown label or constant 'nowhere'");
+        assert_eq!(error("LD V0, 256"), "<input>:1: 256 doesn't fit in a byte");
+        assert_eq!(error("LD I, 0x7FFFFFFFFFFFFFFF + 1"), "<input>:1: '0x7FFFFFFFFFFFFFFF + 1' overflows");
+        assert_eq!(error("LD V0, DT, V1"), "<input>:1: 'LD V0, DT, V1' isn't a valid instruction");
+        assert_eq!(error("a: CLS\na: CLS"), "<input>:2: 'a' is already defined");
+        assert_eq!(error("a equ b\nb equ a\nJP a"), "<input>:3: 'a' is defined in terms of itself");
+    }
+
+    #[test]
+    fn includes_files_relative_to_the_includer() {
+        let dir = std::env::temp_dir().join(format!("chip8_asm_test_{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.asm"), "include \"lib/sub.asm\"\nJP sub").unwrap();
+        fs::write(dir.join("lib/sub.asm"), "sub: RET\nJP missing").unwrap();
+
+        let error = assemble_file(&dir.join("main.asm")).unwrap_err();
+        assert_eq!((error.file.ends_with("sub.asm"), error.line), (true, 2));
+
+        fs::write(dir.join("lib/sub.asm"), "sub: RET").unwrap();
+        assert_eq!(assemble_file(&dir.join("main.asm")), Ok(vec![0x00, 0xEE, 0x12, 0x00]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
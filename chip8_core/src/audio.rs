@@ -0,0 +1,136 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Bytes in the XO-CHIP audio pattern buffer (128 1-bit samples)
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+
+/// Pitch register value playing the pattern at 4000 samples per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Played by ROMs that never load a pattern: a 500 Hz square wave
+pub const DEFAULT_PATTERN: [u8; AUDIO_PATTERN_SIZE] = [0xF0; AUDIO_PATTERN_SIZE];
+
+/// XO-CHIP sound generator. The pattern buffer is played in a loop,
+/// one bit after another starting from the most significant one, as
+/// long as the sound timer is running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audio {
+    pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    position: f64, // Bit of the pattern being played, with the fraction of it already played
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Audio {
+
+    pub fn new() -> Self {
+        Self {
+            pattern: DEFAULT_PATTERN,
+            pitch: DEFAULT_PITCH,
+            position: 0.0,
+        }
+    }
+
+    pub fn pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.pattern
+    }
+
+    pub fn set_pattern(&mut self, pattern: [u8; AUDIO_PATTERN_SIZE]) {
+        self.pattern = pattern;
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    pub(crate) fn save(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.pattern);
+        writer.u8(self.pitch);
+        writer.f64(self.position);
+    }
+
+    pub(crate) fn restore(reader: &mut StateReader) -> Result<Self, StateError> {
+        let pattern = reader.array::<AUDIO_PATTERN_SIZE>()?;
+        let pitch: u8 = reader.u8()?;
+        let position: f64 = reader.f64()?;
+
+        if !(0.0..(AUDIO_PATTERN_SIZE * 8) as f64).contains(&position) {
+            return Err(StateError::InvalidValue { field: "audio position" });
+        }
+        Ok(Self { pattern, pitch, position })
+    }
+
+    /// Pattern bits played per second: 4000 * 2 ^ ((pitch - 64) / 48)
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    /// Fills out with samples in the [-1, 1] range, or with silence
+    /// when not playing or without a sample rate. The pattern position
+    /// carries over between calls, so consecutive buffers join without clicks.
+    pub fn fill(&mut self, out: &mut [f32], sample_rate: u32, playing: bool) {
+        if !playing || sample_rate == 0 {
+            out.fill(0.0);
+            return;
+        }
+
+        let num_bits: f64 = (AUDIO_PATTERN_SIZE * 8) as f64;
+        let step: f64 = self.playback_rate() / sample_rate as f64;
+
+        for sample in out.iter_mut() {
+            let bit: usize = self.position as usize;
+            let set: bool = self.pattern[bit / 8] & (0b1000_0000 >> (bit % 8)) != 0;
+            *sample = if set { 1.0 } else { -1.0 };
+
+            self.position = (self.position + step) % num_bits;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_pattern_is_a_500hz_square_wave() {
+        let mut audio = Audio::new();
+        let mut out = [0.0; 16];
+        audio.fill(&mut out, 4000, true);
+
+        assert_eq!(&out[..8], &[1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+        assert_eq!(&out[..8], &out[8..]);
+    }
+
+    #[test]
+    fn pitch_scales_the_playback_rate() {
+        let mut audio = Audio::new();
+        audio.set_pitch(DEFAULT_PITCH + 48);
+        assert_eq!(audio.playback_rate(), 8000.0);
+
+        let mut out = [1.0; 4];
+        audio.fill(&mut out, 8000, false);
+        assert_eq!(out, [0.0; 4]);
+    }
+
+    #[test]
+    fn zero_sample_rate_is_silent_and_keeps_the_state_valid() {
+        let mut audio = Audio::new();
+        let mut out = [1.0; 4];
+        audio.fill(&mut out, 0, true);
+        assert_eq!(out, [0.0; 4]);
+
+        let mut writer = StateWriter::new();
+        audio.save(&mut writer);
+        let data: Vec<u8> = writer.finish();
+        let mut reader = StateReader::new(&data).unwrap();
+        assert_eq!(Audio::restore(&mut reader).map(|restored| restored.position), Ok(0.0));
+    }
+}
@@ -0,0 +1,33 @@
+use chip8_core::asm::assemble_file;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: chip8-asm path/to/source.asm [path/to/output.ch8]");
+        process::exit(1);
+    }
+
+    let source: &Path = Path::new(&args[1]);
+    let output: PathBuf = match args.get(2) {
+        Some(path) => PathBuf::from(path),
+        None => source.with_extension("ch8"),
+    };
+
+    let rom = match assemble_file(source) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    if let Err(err) = fs::write(&output, &rom) {
+        eprintln!("Couldn't write {}: {}", output.display(), err);
+        process::exit(1);
+    }
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+}
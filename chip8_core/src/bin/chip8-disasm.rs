@@ -0,0 +1,41 @@
+use chip8_core::disasm::disassemble;
+use chip8_core::emulator::START_ADDR;
+use std::env;
+use std::fs;
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: chip8-disasm path/to/game [base_addr]");
+        return;
+    }
+
+    let base_addr: u16 = match args.get(2).map(|arg| u16::from_str_radix(arg.trim_start_matches("0x"), 16)) {
+        None => START_ADDR,
+        Some(Ok(addr)) => addr,
+        Some(Err(_)) => {
+            eprintln!("base_addr must be a hex address, got {}", args[2]);
+            return;
+        }
+    };
+
+    let rom = match fs::read(&args[1]) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Couldn't read {}: {}", args[1], err);
+            return;
+        }
+    };
+
+    for line in disassemble(&rom, base_addr) {
+        if let Some(label) = &line.label {
+            println!("{}:", label);
+        }
+        // Data bytes are already spelled out by DB
+        let bytes: String = match line.instruction {
+            Some(_) => line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect(),
+            None => String::new(),
+        };
+        println!("    {:04X}  {:<8}  {}", line.addr, bytes, line.text);
+    }
+}
@@ -0,0 +1,54 @@
+use chip8_core::debugger::Debugger;
+use chip8_core::emulator::Emulator;
+use chip8_core::gdb::GdbServer;
+use std::env;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+
+const DEFAULT_PORT: &str = "1234";
+
+/// Serves gdb over TCP on localhost, or over stdin/stdout with "-",
+/// for gdb's `target remote | chip8-gdbserver game -`
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: chip8-gdbserver path/to/game [port | -]");
+        return;
+    }
+
+    let rom = match fs::read(&args[1]) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Couldn't read {}: {}", args[1], err);
+            return;
+        }
+    };
+
+    let mut chip8 = Emulator::default();
+    if let Err(err) = chip8.load(&rom) {
+        eprintln!("Couldn't load {}: {}", args[1], err);
+        return;
+    }
+    let mut server = GdbServer::new(Debugger::new(chip8));
+
+    let port: &str = args.get(2).map_or(DEFAULT_PORT, |port| port.as_str());
+    let result = if port == "-" {
+        server.serve(io::stdin(), io::stdout())
+    } else {
+        serve_tcp(&mut server, port)
+    };
+
+    if let Err(err) = result {
+        eprintln!("Connection lost: {}", err);
+    }
+}
+
+fn serve_tcp(server: &mut GdbServer, port: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
+    eprintln!("Waiting for gdb on 127.0.0.1:{}", port);
+
+    let (stream, addr) = listener.accept()?;
+    eprintln!("Debugging for {}", addr);
+    server.serve(stream.try_clone()?, stream)
+}
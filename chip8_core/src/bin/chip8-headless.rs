@@ -0,0 +1,178 @@
+use chip8_core::dump;
+use chip8_core::emulator::{Emulator, NUM_KEYS};
+use chip8_core::quirks::Quirks;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+const USAGE: &str = "Usage: chip8-headless path/to/game [--frames N] [--ipf N] [--timing ipf|vip] [--seed N] \
+[--quirks vip|chip48|schip|xochip] [--press FRAME:KEY[:FRAMES]]... \
+[--png out.png] [--scale N] [--ascii out.txt|-] [--json out.json|-]";
+
+/// A key held down from frame until frame + frames
+struct Press {
+    frame: usize,
+    key: usize,
+    frames: usize,
+}
+
+struct Options {
+    rom: String,
+    frames: usize,
+    ipf: usize,
+    vip_timing: bool,
+    seed: u64,
+    quirks: Quirks,
+    presses: Vec<Press>,
+    png: Option<String>,
+    scale: usize,
+    ascii: Option<String>,
+    json: Option<String>,
+}
+
+fn main() {
+    let options: Options = match parse_args(env::args().skip(1).collect()) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let rom = match fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Couldn't read {}: {}", options.rom, err);
+            process::exit(1);
+        }
+    };
+
+    let mut emulator = Emulator::with_seed(options.quirks, options.seed);
+    emulator.enable_decode_cache();
+    emulator.enable_recompiler();
+    if options.vip_timing {
+        emulator.enable_vip_timing();
+    }
+    if let Err(err) = emulator.load(&rom) {
+        eprintln!("Couldn't load {}: {}", options.rom, err);
+        process::exit(1);
+    }
+
+    // The outputs are still written when the ROM crashes, they help finding out why
+    let mut result = Ok(());
+    for frame in 0..options.frames {
+        for key in 0..NUM_KEYS {
+            let held: bool = options
+                .presses
+                .iter()
+                .any(|press| press.key == key && (press.frame..press.frame + press.frames).contains(&frame));
+            emulator.keypress(key, held);
+        }
+        result = emulator.run_frame(options.ipf).map_err(|err| format!("Frame {}: {}", frame, err));
+        if result.is_err() || emulator.is_halted() {
+            break;
+        }
+    }
+
+    // Without any output option, both the ASCII art and the registers go to stdout
+    let (mut ascii, mut json) = (options.ascii, options.json);
+    if options.png.is_none() && ascii.is_none() && json.is_none() {
+        ascii = Some("-".to_string());
+        json = Some("-".to_string());
+    }
+
+    let mut outputs: Vec<(String, Vec<u8>)> = Vec::new();
+    if let Some(path) = options.png {
+        outputs.push((path, dump::png(emulator.get_display(), options.scale)));
+    }
+    if let Some(path) = ascii {
+        outputs.push((path, dump::ascii(emulator.get_display()).into_bytes()));
+    }
+    if let Some(path) = json {
+        outputs.push((path, dump::registers_json(&emulator).into_bytes()));
+    }
+
+    for (path, data) in outputs {
+        let written = match path.as_str() {
+            "-" => io::stdout().write_all(&data),
+            _ => fs::write(&path, &data),
+        };
+        if let Err(err) = written {
+            eprintln!("Couldn't write {}: {}", path, err);
+            process::exit(1);
+        }
+    }
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut args = args.into_iter();
+    let mut options = Options {
+        rom: args.next().filter(|rom| !rom.starts_with("--")).ok_or("Missing the ROM path")?,
+        frames: 60,
+        ipf: 10,
+        vip_timing: false,
+        seed: 0,
+        quirks: Quirks::default(),
+        presses: Vec::new(),
+        png: None,
+        scale: 1,
+        ascii: None,
+        json: None,
+    };
+
+    while let Some(flag) = args.next() {
+        let value: String = args.next().ok_or(format!("{} needs a value", flag))?;
+        let number = |value: &str| value.parse::<usize>().map_err(|_| format!("{} must be a number, got {}", flag, value));
+
+        match flag.as_str() {
+            "--frames" => options.frames = number(&value)?,
+            "--ipf" => options.ipf = number(&value)?,
+            "--timing" => {
+                options.vip_timing = match value.as_str() {
+                    "ipf" => false,
+                    "vip" => true,
+                    _ => return Err(format!("Unknown timing {}", value)),
+                }
+            }
+            "--seed" => options.seed = number(&value)? as u64,
+            "--scale" => options.scale = number(&value)?,
+            "--quirks" => {
+                options.quirks = Quirks::from_name(&value).ok_or(format!("Unknown quirks profile {}", value))?
+            }
+            "--press" => options.presses.push(parse_press(&value)?),
+            "--png" => options.png = Some(value),
+            "--ascii" => options.ascii = Some(value),
+            "--json" => options.json = Some(value),
+            _ => return Err(format!("Unknown option {}", flag)),
+        }
+    }
+
+    Ok(options)
+}
+
+/// FRAME:KEY[:FRAMES], with the key in hex and held for a single frame by default
+fn parse_press(value: &str) -> Result<Press, String> {
+    let invalid = || format!("--press takes FRAME:KEY[:FRAMES], got {}", value);
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err(invalid());
+    }
+
+    let frame: usize = parts[0].parse().map_err(|_| invalid())?;
+    let key: usize = usize::from_str_radix(parts[1], 16).map_err(|_| invalid())?;
+    let frames: usize = match parts.get(2) {
+        Some(frames) => frames.parse().map_err(|_| invalid())?,
+        None => 1,
+    };
+    if key >= NUM_KEYS {
+        return Err(format!("Keys go from 0 to F, got {}", parts[1]));
+    }
+
+    Ok(Press { frame, key, frames })
+}
@@ -0,0 +1,68 @@
+use crate::emulator::RAM_SIZE;
+use crate::operations::Instruction;
+
+/// The opcode and decoded instruction at every address that ran
+/// since the last write to it, so loops skip fetching and decoding.
+///
+/// Instructions are 2 bytes long, so a write to addr invalidates the
+/// ones starting at addr and addr - 1. The address word of an XO-CHIP
+/// long load isn't part of its decoded instruction, so it needs nothing.
+pub struct DecodeCache {
+    entries: Vec<Option<(u16, Instruction)>>,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecodeCache {
+
+    pub fn new() -> Self {
+        Self { entries: vec![None; RAM_SIZE] }
+    }
+
+    pub fn get(&self, addr: u16) -> Option<(u16, Instruction)> {
+        self.entries[addr as usize]
+    }
+
+    pub fn insert(&mut self, addr: u16, op: u16, instruction: Instruction) {
+        self.entries[addr as usize] = Some((op, instruction));
+    }
+
+    /// Forgets the instructions overlapping the byte at addr
+    pub fn invalidate(&mut self, addr: usize) {
+        if let Some(entry) = self.entries.get_mut(addr) {
+            *entry = None;
+        }
+        if let Some(entry) = addr.checked_sub(1).and_then(|addr| self.entries.get_mut(addr)) {
+            *entry = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_invalidate_overlapping_instructions() {
+        let mut cache = DecodeCache::new();
+        cache.insert(0x200, 0x00E0, Instruction::ClearScreen);
+        cache.insert(0x202, 0x00E0, Instruction::ClearScreen);
+        cache.insert(0x204, 0x00E0, Instruction::ClearScreen);
+
+        cache.invalidate(0x203);
+        assert!(cache.get(0x200).is_some());
+        assert_eq!(cache.get(0x202), None);
+        assert!(cache.get(0x204).is_some());
+
+        cache.invalidate(0x204);
+        assert_eq!(cache.get(0x204), None);
+    }
+}
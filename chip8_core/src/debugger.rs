@@ -0,0 +1,355 @@
+use crate::emulator::Emulator;
+use crate::error::EmulatorError;
+use crate::operations::{decode, Instruction};
+use std::ops::Range;
+
+/// Registers a conditional breakpoint can look at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Dt,
+    St,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+/// `register comparison value`, e.g. V3 == 0x10
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    fn holds(&self, emu: &Emulator) -> bool {
+        let current: u16 = match self.register {
+            Register::V(idx) => emu.v_reg()[idx as usize & 0xF] as u16,
+            Register::I => emu.i_reg(),
+            Register::Dt => emu.dt() as u16,
+            Register::St => emu.st() as u16,
+        };
+
+        match self.comparison {
+            Comparison::Equal => current == self.value,
+            Comparison::NotEqual => current != self.value,
+            Comparison::Less => current < self.value,
+            Comparison::Greater => current > self.value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    /// Only stop if it holds
+    pub condition: Option<Condition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Stops after an instruction reads or writes any byte in addrs.
+/// Instruction fetches don't count.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addrs: Range<usize>,
+    pub read: bool,
+    pub write: bool,
+}
+
+/// Why the debugger handed control back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested step (or steps) finished
+    Step,
+    /// About to run the instruction at addr
+    Breakpoint { addr: u16 },
+    /// The last instruction touched a watched address
+    Watchpoint { addr: usize, access: Access },
+    /// The instruction at pc failed, and wasn't run
+    Error(EmulatorError),
+    /// The program exited
+    Halted,
+    /// Ran max_ticks instructions without stopping
+    TickLimit,
+}
+
+/// Wraps an Emulator to run it instruction by instruction, stopping
+/// at breakpoints and watchpoints.
+///
+/// The timers tick every ticks_per_frame instructions, so programs
+/// waiting on the delay timer still make progress.
+pub struct Debugger {
+    emu: Emulator,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    ticks_per_frame: usize,
+    ticks: usize, // Instructions since the last timers tick
+}
+
+impl Debugger {
+
+    pub fn new(emu: Emulator) -> Self {
+        Self {
+            emu,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            ticks_per_frame: 10,
+            ticks: 0,
+        }
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emu
+    }
+
+    /// For loading ROMs, pressing keys, or poking at the state
+    pub fn emulator_mut(&mut self) -> &mut Emulator {
+        &mut self.emu
+    }
+
+    pub fn into_inner(self) -> Emulator {
+        self.emu
+    }
+
+    pub fn set_ticks_per_frame(&mut self, ticks_per_frame: usize) {
+        self.ticks_per_frame = ticks_per_frame.max(1);
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.push(Breakpoint { addr, condition: None });
+    }
+
+    pub fn add_conditional_breakpoint(&mut self, addr: u16, condition: Condition) {
+        self.breakpoints.push(Breakpoint { addr, condition: Some(condition) });
+    }
+
+    /// Removes every breakpoint at addr, conditional or not
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.retain(|breakpoint| breakpoint.addr != addr);
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, addrs: Range<usize>, read: bool, write: bool) {
+        self.watchpoints.push(Watchpoint { addrs, read, write });
+    }
+
+    pub fn remove_watchpoint(&mut self, addrs: Range<usize>) {
+        self.watchpoints.retain(|watchpoint| watchpoint.addrs != addrs);
+    }
+
+    /// The instruction at pc, if it decodes
+    pub fn next_instruction(&self) -> Option<Instruction> {
+        let pc: usize = self.emu.pc() as usize;
+        let ram: &[u8] = self.emu.ram();
+        let op: u16 = ((*ram.get(pc)? as u16) << 8) | *ram.get(pc + 1)? as u16;
+        decode(op).ok()
+    }
+
+    /// Runs a single instruction, ignoring breakpoints
+    pub fn step(&mut self) -> StopReason {
+        match self.tick() {
+            Some(reason) => reason,
+            None => StopReason::Step,
+        }
+    }
+
+    /// Like step, but runs a whole CALL until it returns
+    pub fn step_over(&mut self, max_ticks: usize) -> StopReason {
+        if !matches!(self.next_instruction(), Some(Instruction::Call { .. })) {
+            return self.step();
+        }
+
+        let sp: u16 = self.emu.sp();
+        let return_addr: u16 = self.emu.pc().wrapping_add(2);
+        self.run_until(max_ticks, |emu| emu.sp() == sp && emu.pc() == return_addr)
+    }
+
+    /// Runs until the current subroutine returns to its caller
+    pub fn step_out(&mut self, max_ticks: usize) -> StopReason {
+        let sp: u16 = self.emu.sp();
+        if sp == 0 {
+            return self.step();
+        }
+        self.run_until(max_ticks, |emu| emu.sp() < sp)
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, or up to max_ticks
+    /// instructions. The instruction at pc always runs, even if there's
+    /// a breakpoint on it, so continuing from a breakpoint works.
+    pub fn run(&mut self, max_ticks: usize) -> StopReason {
+        self.run_until(max_ticks, |_| false)
+    }
+
+    fn run_until(&mut self, max_ticks: usize, done: impl Fn(&Emulator) -> bool) -> StopReason {
+        for count in 0..max_ticks {
+            if count > 0 {
+                if let Some(addr) = self.breakpoint_hit() {
+                    return StopReason::Breakpoint { addr };
+                }
+            }
+            if let Some(reason) = self.tick() {
+                return reason;
+            }
+            if done(&self.emu) {
+                return StopReason::Step;
+            }
+        }
+        StopReason::TickLimit
+    }
+
+    /// The breakpoint at pc, if there's one and its condition holds
+    pub fn breakpoint_hit(&self) -> Option<u16> {
+        let pc: u16 = self.emu.pc();
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| {
+                breakpoint.addr == pc && breakpoint.condition.is_none_or(|condition| condition.holds(&self.emu))
+            })
+            .map(|breakpoint| breakpoint.addr)
+    }
+
+    /// Runs an instruction, ticking the timers when a frame's worth
+    /// went by. Returns why the debugger should stop, if it should.
+    fn tick(&mut self) -> Option<StopReason> {
+        if self.emu.is_halted() {
+            return Some(StopReason::Halted);
+        }
+
+        // The accesses depend on I and the registers before running
+        let accesses = self.next_instruction().map(|instruction| memory_accesses(&self.emu, instruction));
+
+        if let Err(err) = self.emu.tick() {
+            return Some(StopReason::Error(err));
+        }
+        self.ticks += 1;
+        if self.ticks >= self.ticks_per_frame {
+            self.ticks = 0;
+            self.emu.tick_timers();
+        }
+
+        let (addrs, access) = accesses??;
+        self.watchpoints.iter().find_map(|watchpoint| {
+            let watched: bool = match access {
+                Access::Read => watchpoint.read,
+                Access::Write => watchpoint.write,
+            };
+            let addr: usize = addrs.clone().find(|addr| watchpoint.addrs.contains(addr))?;
+            watched.then_some(StopReason::Watchpoint { addr, access })
+        })
+    }
+}
+
+/// The RAM bytes the instruction reads or writes, given the current state.
+/// CHIP-8 instructions never do both.
+pub fn memory_accesses(emu: &Emulator, instruction: Instruction) -> Option<(Range<usize>, Access)> {
+    let i: usize = emu.i_reg() as usize;
+
+    let (len, access): (usize, Access) = match instruction {
+        Instruction::DrawSprite { n, .. } => {
+            let sprite_len: usize = if n == 0 { 32 } else { n as usize };
+            let num_planes: usize = emu.get_display().planes().count_ones() as usize;
+            (sprite_len * num_planes, Access::Read)
+        }
+        Instruction::SetRamPointerToBcdOfReg { .. } => (3, Access::Write),
+        Instruction::StoreRegsInRam { x } => (x as usize + 1, Access::Write),
+        Instruction::LoadRegsFromRam { x } => (x as usize + 1, Access::Read),
+        Instruction::StoreRegRangeInRam { x, y } => (x.abs_diff(y) as usize + 1, Access::Write),
+        Instruction::LoadRegRangeFromRam { x, y } => (x.abs_diff(y) as usize + 1, Access::Read),
+        Instruction::LoadAudioPattern => (16, Access::Read),
+        _ => return None,
+    };
+
+    Some((i..i + len, access))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    /// 0x200: V0 = 5
+    /// 0x202: CALL 0x20A
+    /// 0x204: V1 = 1
+    /// 0x206: JMP 0x206
+    /// 0x208: (padding)
+    /// 0x20A: I = 0x300
+    /// 0x20C: STORE V0..V0
+    /// 0x20E: RET
+    const ROM: [u8; 16] = [
+        0x60, 0x05, 0x22, 0x0A, 0x61, 0x01, 0x12, 0x06,
+        0x00, 0x00, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xEE,
+    ];
+
+    fn debugger() -> Debugger {
+        let mut emu = Emulator::new(Quirks::super_chip());
+        emu.load(&ROM).unwrap();
+        Debugger::new(emu)
+    }
+
+    #[test]
+    fn stops_at_breakpoints_and_continues_past_them() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(0x20C);
+
+        assert_eq!(debugger.run(100), StopReason::Breakpoint { addr: 0x20C });
+        assert_eq!(debugger.emulator().sp(), 1);
+        assert_eq!(debugger.run(100), StopReason::TickLimit);
+    }
+
+    #[test]
+    fn conditional_breakpoints_check_registers() {
+        let mut debugger = debugger();
+        let never = Condition { register: Register::V(0), comparison: Comparison::Greater, value: 5 };
+        let always = Condition { register: Register::V(1), comparison: Comparison::Equal, value: 1 };
+        debugger.add_conditional_breakpoint(0x204, never);
+        debugger.add_conditional_breakpoint(0x206, always);
+
+        assert_eq!(debugger.run(100), StopReason::Breakpoint { addr: 0x206 });
+        assert_eq!(debugger.emulator().v_reg()[1], 1);
+    }
+
+    #[test]
+    fn watchpoints_report_the_accessed_address() {
+        let mut debugger = debugger();
+        debugger.add_watchpoint(0x300..0x301, false, true);
+
+        assert_eq!(debugger.run(100), StopReason::Watchpoint { addr: 0x300, access: Access::Write });
+        assert_eq!(debugger.emulator().pc(), 0x20E);
+        assert_eq!(debugger.emulator().ram()[0x300], 5);
+    }
+
+    #[test]
+    fn steps_over_and_out_of_calls() {
+        let mut debugger = debugger();
+        debugger.step();
+
+        assert_eq!(debugger.step_over(100), StopReason::Step);
+        assert_eq!((debugger.emulator().pc(), debugger.emulator().sp()), (0x204, 0));
+
+        let mut debugger = self::debugger();
+        debugger.step();
+        debugger.step();
+        assert_eq!(debugger.emulator().pc(), 0x20A);
+        assert_eq!(debugger.step_out(100), StopReason::Step);
+        assert_eq!(debugger.emulator().pc(), 0x204);
+    }
+}
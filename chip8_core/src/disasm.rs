@@ -0,0 +1,205 @@
+use crate::operations::{decode, Instruction};
+use std::collections::BTreeMap;
+
+/// Most data bytes on a single DB line
+const DATA_LINE_LEN: usize = 8;
+
+/// A line of a listing: an instruction, or a run of data bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    /// None for data
+    pub instruction: Option<Instruction>,
+    /// Set when a jump, call or LD I targets this line
+    pub label: Option<String>,
+    /// The mnemonic, using labels for the addresses that have one
+    pub text: String,
+}
+
+/// Disassembles a ROM loaded at base_addr, running from its first byte.
+///
+/// Jumps, calls and skips are followed to find out which bytes are
+/// code; whatever is never reached, like sprites, is listed as data.
+/// BNNN jumps can't be followed, so the code they lead to shows as data.
+pub fn disassemble(rom: &[u8], base_addr: u16) -> Vec<Line> {
+    let mut code: Vec<bool> = vec![false; rom.len()]; // Instruction starts
+    let mut labels: BTreeMap<u16, String> = BTreeMap::new();
+    let mut pending: Vec<usize> = vec![0];
+
+    while let Some(offset) = pending.pop() {
+        if code.get(offset) != Some(&false) {
+            continue;
+        }
+        let Some(instruction) = instruction_at(rom, offset) else {
+            continue;
+        };
+        code[offset] = true;
+
+        let next: usize = offset + instruction_len(instruction);
+        let offset_of = |target: u16| target.wrapping_sub(base_addr) as usize;
+
+        match instruction {
+            Instruction::Jump { nnn } => {
+                labels.entry(nnn).or_insert_with(|| format!("L{:03X}", nnn));
+                pending.push(offset_of(nnn));
+            }
+            Instruction::Call { nnn } => {
+                labels.insert(nnn, format!("sub_{:03X}", nnn));
+                pending.push(offset_of(nnn));
+                pending.push(next);
+            }
+            Instruction::AssignAddrToRamPointer { nnn } => {
+                labels.entry(nnn).or_insert_with(|| format!("data_{:03X}", nnn));
+                pending.push(next);
+            }
+            Instruction::Return | Instruction::Exit | Instruction::JumpWithOffset { .. } => {}
+            Instruction::SkipIfRegEqualsVal { .. }
+            | Instruction::SkipIfRegNotEqualsVal { .. }
+            | Instruction::SkipIfRegEqualsReg { .. }
+            | Instruction::SkipIfRegNotEqualsReg { .. }
+            | Instruction::SkipIfKeyPressed { .. }
+            | Instruction::SkipIfKeyNotPressed { .. } => {
+                pending.push(next);
+                if let Some(skipped) = instruction_at(rom, next) {
+                    pending.push(next + instruction_len(skipped));
+                }
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    // Only addresses that start a line get a label, so that every label
+    // gets defined: the others, like the font or the middle of an
+    // instruction, stay plain numbers
+    let mut inside: Vec<bool> = vec![false; rom.len()]; // Past the first byte of an instruction
+    for offset in (0..rom.len()).filter(|&offset| code[offset]) {
+        if let Some(instruction) = instruction_at(rom, offset) {
+            inside[offset + 1..offset + instruction_len(instruction)].fill(true);
+        }
+    }
+    labels.retain(|&addr, _| {
+        let offset: usize = addr.wrapping_sub(base_addr) as usize;
+        offset < rom.len() && !inside[offset]
+    });
+
+    let mut lines: Vec<Line> = Vec::new();
+    let mut offset: usize = 0;
+    while offset < rom.len() {
+        let addr: u16 = base_addr.wrapping_add(offset as u16);
+        let label: Option<String> = labels.get(&addr).cloned();
+
+        let line: Line = match instruction_at(rom, offset).filter(|_| code[offset]) {
+            Some(instruction) => {
+                let len: usize = instruction_len(instruction);
+                Line {
+                    addr,
+                    bytes: rom[offset..offset + len].to_vec(),
+                    instruction: Some(instruction),
+                    label,
+                    text: mnemonic(instruction, &rom[offset..offset + len], &labels),
+                }
+            }
+            None => {
+                // Data runs until the next instruction or label
+                let mut end: usize = offset + 1;
+                while end < rom.len()
+                    && end - offset < DATA_LINE_LEN
+                    && !code[end]
+                    && !labels.contains_key(&base_addr.wrapping_add(end as u16))
+                {
+                    end += 1;
+                }
+                let bytes: Vec<u8> = rom[offset..end].to_vec();
+                let text: String = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect::<Vec<_>>().join(", ");
+                Line {
+                    addr,
+                    bytes,
+                    instruction: None,
+                    label,
+                    text: format!("DB {}", text),
+                }
+            }
+        };
+
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// The instruction starting at offset, if the ROM holds all of it
+fn instruction_at(rom: &[u8], offset: usize) -> Option<Instruction> {
+    let op: u16 = ((*rom.get(offset)? as u16) << 8) | *rom.get(offset + 1)? as u16;
+    let instruction: Instruction = decode(op).ok()?;
+    if offset + instruction_len(instruction) > rom.len() {
+        return None;
+    }
+    Some(instruction)
+}
+
+/// F000 NNNN takes 4 bytes, everything else 2
+fn instruction_len(instruction: Instruction) -> usize {
+    match instruction {
+        Instruction::LongAssignAddrToRamPointer => 4,
+        _ => 2,
+    }
+}
+
+fn mnemonic(instruction: Instruction, bytes: &[u8], labels: &BTreeMap<u16, String>) -> String {
+    let target = |nnn: u16| labels.get(&nnn).cloned().unwrap_or_else(|| format!("{:#05X}", nnn));
+
+    match instruction {
+        Instruction::Jump { nnn } => format!("JP {}", target(nnn)),
+        Instruction::Call { nnn } => format!("CALL {}", target(nnn)),
+        Instruction::AssignAddrToRamPointer { nnn } => format!("LD I, {}", target(nnn)),
+        Instruction::LongAssignAddrToRamPointer => {
+            format!("LD I, LONG {:#06X}", ((bytes[2] as u16) << 8) | bytes[3] as u16)
+        }
+        _ => instruction.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_code_from_sprite_data() {
+        // 0x200: CALL 0x208
+        // 0x202: SE V0, 0x00
+        // 0x204: JP 0x202
+        // 0x206: JP 0x206
+        // 0x208: LD I, 0x20E
+        // 0x20A: DRW V0, V0, 2
+        // 0x20C: RET
+        // 0x20E: sprite
+        let rom: [u8; 16] = [
+            0x22, 0x08, 0x30, 0x00, 0x12, 0x02, 0x12, 0x06,
+            0xA2, 0x0E, 0xD0, 0x02, 0x00, 0xEE, 0xF0, 0x90,
+        ];
+        let lines = disassemble(&rom, 0x200);
+
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, [
+            "CALL sub_208", "SE V0, 0x00", "JP L202", "JP L206",
+            "LD I, data_20E", "DRW V0, V0, 2", "RET", "DB 0xF0, 0x90",
+        ]);
+        assert_eq!(lines[4].label.as_deref(), Some("sub_208"));
+        assert_eq!(lines[7].label.as_deref(), Some("data_20E"));
+        assert_eq!(lines[7].instruction, None);
+    }
+
+    #[test]
+    fn unreached_bytes_are_data_even_if_they_decode() {
+        // JP 0x204, then an unreachable CLS, then LD I, LONG 0x1234
+        let rom: [u8; 8] = [0x12, 0x04, 0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34];
+        let lines = disassemble(&rom, 0x200);
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].text, "DB 0x00, 0xE0");
+        assert_eq!(lines[2].text, "LD I, LONG 0x1234");
+        assert_eq!(lines[2].bytes.len(), 4);
+    }
+}
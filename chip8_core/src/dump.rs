@@ -0,0 +1,161 @@
+use crate::emulator::Emulator;
+use crate::screen::Screen;
+
+/// ASCII characters for each pixel value
+pub const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+/// RGB colors for each pixel value, the desktop app's palette
+pub const PNG_PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Longest stored (uncompressed) deflate block
+const MAX_STORED_BLOCK: usize = 65535;
+
+/// A line of text per row of pixels, see ASCII_PIXELS
+pub fn ascii(screen: &Screen) -> String {
+    let mut text: String = String::with_capacity((screen.width() + 1) * screen.height());
+    for row in screen.pixels().chunks(screen.width()) {
+        text.extend(row.iter().map(|&pixel| ASCII_PIXELS[pixel as usize % ASCII_PIXELS.len()]));
+        text.push('\n');
+    }
+    text
+}
+
+/// A PNG image of the screen, every pixel scaled up to scale x scale.
+/// The pixels are stored uncompressed, as screens are tiny anyway.
+pub fn png(screen: &Screen, scale: usize) -> Vec<u8> {
+    let scale: usize = scale.max(1);
+    let width: usize = screen.width() * scale;
+    let height: usize = screen.height() * scale;
+
+    // Every row starts with its filter type, 0 for none
+    let mut raw: Vec<u8> = Vec::with_capacity((width + 1) * height);
+    for row in screen.pixels().chunks(screen.width()) {
+        let mut line: Vec<u8> = vec![0];
+        for &pixel in row {
+            line.extend(std::iter::repeat_n(pixel % PNG_PALETTE.len() as u8, scale));
+        }
+        for _ in 0..scale {
+            raw.extend(&line);
+        }
+    }
+
+    let mut header: Vec<u8> = Vec::new();
+    header.extend((width as u32).to_be_bytes());
+    header.extend((height as u32).to_be_bytes());
+    // 8 bits per pixel, indexed colors, and the default compression, filter and interlace methods
+    header.extend([8, 3, 0, 0, 0]);
+
+    let mut image: Vec<u8> = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut image, b"IHDR", &header);
+    write_chunk(&mut image, b"PLTE", PNG_PALETTE.as_flattened());
+    write_chunk(&mut image, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut image, b"IEND", &[]);
+    image
+}
+
+/// The registers and timers as a JSON object, for scripts to check
+pub fn registers_json(emu: &Emulator) -> String {
+    let list = |values: &mut dyn Iterator<Item = u16>| values.map(|value| value.to_string()).collect::<Vec<_>>().join(", ");
+
+    format!(
+        "{{\n  \"pc\": {},\n  \"i\": {},\n  \"v\": [{}],\n  \"sp\": {},\n  \"stack\": [{}],\n  \"dt\": {},\n  \"st\": {},\n  \"hires\": {},\n  \"halted\": {}\n}}\n",
+        emu.pc(),
+        emu.i_reg(),
+        list(&mut emu.v_reg().iter().map(|&value| value as u16)),
+        emu.sp(),
+        list(&mut emu.stack()[..emu.sp() as usize].iter().copied()),
+        emu.dt(),
+        emu.st(),
+        emu.get_display().is_hires(),
+        emu.is_halted()
+    )
+}
+
+fn write_chunk(image: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    image.extend((data.len() as u32).to_be_bytes());
+    let start: usize = image.len();
+    image.extend(kind);
+    image.extend(data);
+    let crc: u32 = crc32(&image[start..]);
+    image.extend(crc.to_be_bytes());
+}
+
+/// A zlib stream made of stored deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+
+    if blocks.peek().is_none() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last: u8 = blocks.peek().is_none() as u8;
+        let len: u16 = block.len() as u16;
+        out.push(last);
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b): (u32, u32) = (1, 0);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn png_has_the_scaled_size_and_valid_chunks() {
+        let mut screen = Screen::new();
+        screen.flip(1, 0, 1);
+        let image = png(&screen, 2);
+
+        assert_eq!(&image[..8], &PNG_SIGNATURE);
+        assert_eq!(&image[16..24], &[0, 0, 0, 128, 0, 0, 0, 64]);
+        assert_eq!(&image[image.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+
+        // The first row: filter byte, 2 black pixels, 2 white ones
+        let idat: usize = image.windows(4).position(|kind| kind == b"IDAT").unwrap();
+        assert_eq!(&image[idat + 4 + 7..idat + 4 + 12], &[0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn ascii_has_a_line_per_row() {
+        let mut screen = Screen::new();
+        screen.flip(2, 1, 1);
+        let text = ascii(&screen);
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert_eq!(&lines[1][..4], "..#.");
+    }
+}
@@ -1,4 +1,4 @@
-pub const RAM_SIZE: usize = 4096; // 4 KB
+pub const RAM_SIZE: usize = 65536; // 64 KB, as XO-CHIP needs
 pub const NUM_VREGS: usize = 16;
 pub const STACK_SIZE: usize = 16;
 pub const NUM_KEYS: usize = 16;
@@ -6,19 +6,36 @@ pub const NUM_KEYS: usize = 16;
 pub const SCREEN_WIDTH: usize = 64; 
 pub const SCREEN_HEIGHT: usize = 32;
 
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+
 pub const START_ADDR: u16 = 0x200;
 
 pub const FLAG_REG: usize = 0xF;
 
+use crate::audio::{Audio, AUDIO_PATTERN_SIZE};
+use crate::cache::DecodeCache;
+use crate::error::EmulatorError;
 use crate::fontset::*;
+use crate::operations::*;
+use crate::quirks::*;
+use crate::random::{RandomSource, Xorshift};
+use crate::rewind::RewindBuffer;
+use crate::screen::{Screen, NUM_PLANES};
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::timing::{self, VipTiming, INTERPRETER_CYCLES_PER_FRAME};
+use crate::trace::{Registers, TraceEntry, Tracer};
 use rand::random;
+use recompiler::Recompiler;
+
+mod recompiler;
 
 /// We use type uN (where N is a 8 aligned number) because
 /// it defines the amount of bits we need for every number.
 pub struct Emulator {
     pc: u16, // Program Counter
     ram: [u8; RAM_SIZE],
-    screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    screen: Screen,
     v_reg: [u8; NUM_VREGS],
     i_reg: u16, // Pointer used for indexing into RAM
     sp: u16, // Stack Pointer
@@ -26,15 +43,42 @@ pub struct Emulator {
     keys: [bool; NUM_KEYS],
     dt: u8, // Delay timer
     st: u8, // Sound timer
+    rpl: [u8; NUM_VREGS], // SCHIP user flags
+    halted: bool, // Set by the SCHIP exit instruction
+    audio: Audio, // XO-CHIP pattern buffer and pitch
+    rng: Box<dyn RandomSource>, // Source of the CXNN random numbers
+    quirks: Quirks,
+    rewind: Option<RewindBuffer>, // Recent snapshots, when rewinding is enabled
+    tracer: Option<Tracer>, // Records the executed instructions, when tracing is enabled
+    cache: Option<DecodeCache>, // Decoded instructions, when the decode cache is enabled
+    recompiler: Option<Recompiler>, // Compiled blocks, when the recompiler is enabled
+    timing: Option<VipTiming>, // Cycles carried between frames, when VIP timing is enabled
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new(Quirks::default())
+    }
 }
 
 impl Emulator {
 
-    pub fn new() -> Self {
+    /// The random numbers are seeded from the OS, so every run differs.
+    /// Use with_seed for reproducible runs.
+    pub fn new(quirks: Quirks) -> Self {
+        Self::with_seed(quirks, random())
+    }
+
+    /// Same seed, same ROM and same inputs always give the same run
+    pub fn with_seed(quirks: Quirks, seed: u64) -> Self {
+        Self::with_random_source(quirks, Box::new(Xorshift::new(seed)))
+    }
+
+    pub fn with_random_source(quirks: Quirks, rng: Box<dyn RandomSource>) -> Self {
         let mut new_emulator: Emulator = Self {
             pc: START_ADDR,
             ram: [0; RAM_SIZE],
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            screen: Screen::new(),
             v_reg: [0; NUM_VREGS],
             i_reg: 0,
             sp: 0,
@@ -42,76 +86,197 @@ impl Emulator {
             keys: [false; NUM_KEYS],
             dt: 0,
             st: 0,
+            rpl: [0; NUM_VREGS],
+            halted: false,
+            audio: Audio::new(),
+            rng,
+            quirks,
+            rewind: None,
+            tracer: None,
+            cache: None,
+            recompiler: None,
+            timing: None,
         };
 
-        new_emulator.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        new_emulator.load_fonts();
 
         new_emulator
     }
 
     // Stack management functions
 
-    fn push(&mut self, value: u16) {
+    fn push(&mut self, value: u16) -> Result<(), EmulatorError> {
+        if self.sp as usize >= STACK_SIZE {
+            return Err(EmulatorError::StackOverflow);
+        }
         self.stack[self.sp as usize] = value;
         self.sp += 1;
+        Ok(())
     }
 
-    fn pop(&mut self) -> u16 {
+    fn pop(&mut self) -> Result<u16, EmulatorError> {
+        if self.sp == 0 {
+            return Err(EmulatorError::StackUnderflow);
+        }
         self.sp -= 1;
-        self.stack[self.sp as usize]
+        Ok(self.stack[self.sp as usize])
+    }
+
+    // Memory access functions
+
+    fn read_ram(&self, addr: usize) -> Result<u8, EmulatorError> {
+        self.ram
+            .get(addr)
+            .copied()
+            .ok_or(EmulatorError::MemoryOutOfBounds { addr })
+    }
+
+    fn write_ram(&mut self, addr: usize, value: u8) -> Result<(), EmulatorError> {
+        let cell = self.ram
+            .get_mut(addr)
+            .ok_or(EmulatorError::MemoryOutOfBounds { addr })?;
+        *cell = value;
+        if let Some(cache) = self.cache.as_mut() {
+            cache.invalidate(addr);
+        }
+        if let Some(recompiler) = self.recompiler.as_mut() {
+            recompiler.invalidate(addr);
+        }
+        Ok(())
     }
 
     // CPU operation functions
 
-    fn fetch(&mut self) -> u16 {
-        let higher_byte: u16 = self.ram[self.pc as usize] as u16;
-        let lower_byte: u16 = self.ram[(self.pc + 1) as usize] as u16;
-        let op = (higher_byte << 8) | lower_byte;
-        op
+    /// Reads the opcode pointed by the pc, and moves the pc to the next one
+    fn fetch(&mut self) -> Result<u16, EmulatorError> {
+        let higher_byte: u16 = self.read_ram(self.pc as usize)? as u16;
+        let lower_byte: u16 = self.read_ram(self.pc as usize + 1)? as u16;
+        self.pc = self.pc.wrapping_add(2);
+        Ok((higher_byte << 8) | lower_byte)
+    }
+
+    /// Fetches and decodes, going through the decode cache when it's enabled
+    fn fetch_decoded(&mut self) -> Result<(u16, Result<Instruction, DecodeError>), EmulatorError> {
+        let pc: u16 = self.pc;
+        if let Some((op, instruction)) = self.cache.as_ref().and_then(|cache| cache.get(pc)) {
+            self.pc = self.pc.wrapping_add(2);
+            return Ok((op, Ok(instruction)));
+        }
+
+        let op: u16 = self.fetch()?;
+        let decoded = decode(op);
+        if let (Some(cache), Ok(instruction)) = (self.cache.as_mut(), decoded) {
+            cache.insert(pc, op, instruction);
+        }
+        Ok((op, decoded))
+    }
+
+    fn load_fonts(&mut self) {
+        self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        self.ram[BIG_FONTSET_ADDR..BIG_FONTSET_ADDR + BIG_FONTSET_SIZE].copy_from_slice(&BIG_FONTSET);
     }
 
     fn clear_screen(&mut self) {
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT]
+        self.screen.clear();
+    }
+
+    fn scroll_down(&mut self, num_rows: u8) {
+        self.screen.scroll_down(num_rows as usize);
+    }
+
+    fn scroll_up(&mut self, num_rows: u8) {
+        self.screen.scroll_up(num_rows as usize);
+    }
+
+    fn scroll_right(&mut self) {
+        self.screen.scroll_right(4);
+    }
+
+    fn scroll_left(&mut self) {
+        self.screen.scroll_left(4);
+    }
+
+    fn exit(&mut self) {
+        self.halted = true;
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.screen.set_hires(hires);
     }
 
     /// Pops the address from the stack, and set the pc with it
-    fn return_from_subroutine(&mut self) {
-        let ret_addr: u16 = self.pop();
+    fn return_from_subroutine(&mut self) -> Result<(), EmulatorError> {
+        let ret_addr: u16 = self.pop()?;
         self.pc = ret_addr;
+        Ok(())
     }
 
     /// Sets the pc with the nnn address
-    fn jump(&mut self, op: u16) {
-        let nnn = op & 0xFFF;
+    fn jump(&mut self, nnn: u16) {
         self.pc = nnn;
     }
 
     /// Push the last routine address in the stack, and set the pc
     /// with the new subroutine address.
-    fn call(&mut self, op: u16) {
-        let nnn = op & 0xFFF;
-        self.push(self.pc);
+    fn call(&mut self, nnn: u16) -> Result<(), EmulatorError> {
+        self.push(self.pc)?;
         self.pc = nnn;
+        Ok(())
+    }
+
+    /// Moves the pc past the next instruction.
+    /// XO-CHIP long loads are 4 bytes long, so their address is skipped too.
+    fn skip_next(&mut self) {
+        let pc: usize = self.pc as usize;
+        let is_long_load: bool = self.read_ram(pc) == Ok(0xF0) && self.read_ram(pc + 1) == Ok(0x00);
+        let len: u16 = if is_long_load { 4 } else { 2 };
+        self.pc = self.pc.wrapping_add(len);
     }
 
     fn skip_next_if_reg_equals_val(&mut self, idx: usize, val: u8) {
         if self.v_reg[idx] == val {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
     fn skip_next_if_reg_not_equals_val(&mut self, idx: usize, val: u8) {
         if self.v_reg[idx] != val {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
-    fn skip_next_if_reg_equals_reg(&mut self, idxA: usize, idxB: usize) {
-        if self.v_reg[idxA] == self.v_reg[idxB] {
-            self.pc += 2;
+    fn skip_next_if_reg_equals_reg(&mut self, idx_a: usize, idx_b: usize) {
+        if self.v_reg[idx_a] == self.v_reg[idx_b] {
+            self.skip_next();
         }
     }
 
+    /// Registers are stored in the VX..VY order, even if X > Y
+    fn store_reg_range_in_ram(&mut self, idx_a: usize, idx_b: usize) -> Result<(), EmulatorError> {
+        let i: usize = self.i_reg as usize;
+
+        for (offset, x) in Self::reg_range(idx_a, idx_b).enumerate() {
+            self.write_ram(i + offset, self.v_reg[x])?;
+        }
+        Ok(())
+    }
+
+    /// Registers are loaded in the VX..VY order, even if X > Y
+    fn load_reg_range_from_ram(&mut self, idx_a: usize, idx_b: usize) -> Result<(), EmulatorError> {
+        let i: usize = self.i_reg as usize;
+
+        for (offset, x) in Self::reg_range(idx_a, idx_b).enumerate() {
+            self.v_reg[x] = self.read_ram(i + offset)?;
+        }
+        Ok(())
+    }
+
+    fn reg_range(idx_a: usize, idx_b: usize) -> impl Iterator<Item = usize> {
+        (0..=idx_a.abs_diff(idx_b)).map(move |offset| {
+            if idx_a <= idx_b { idx_a + offset } else { idx_a - offset }
+        })
+    }
+
     fn assign_val_to_reg(&mut self, idx: usize, val: u8) {
         self.v_reg[idx] = val;
     }
@@ -120,99 +285,177 @@ impl Emulator {
         self.v_reg[idx] = self.v_reg[idx].wrapping_add(val);
     }
 
-    fn assign_reg_to_reg(&mut self, idxA: usize, idxB: usize) {
-        self.v_reg[idxA] = self.v_reg[idxB];
+    fn assign_reg_to_reg(&mut self, idx_a: usize, idx_b: usize) {
+        self.v_reg[idx_a] = self.v_reg[idx_b];
     }
 
-    fn reg_or_reg(&mut self, idxA: usize, idxB: usize) {
-        self.v_reg[idxA] |= self.v_reg[idxB];
+    fn reg_or_reg(&mut self, idx_a: usize, idx_b: usize) {
+        self.v_reg[idx_a] |= self.v_reg[idx_b];
+        self.reset_flag_after_logic_op();
     }
 
-    fn reg_and_reg(&mut self, idxA: usize, idxB: usize) {
-        self.v_reg[idxA] &= self.v_reg[idxB];
+    fn reg_and_reg(&mut self, idx_a: usize, idx_b: usize) {
+        self.v_reg[idx_a] &= self.v_reg[idx_b];
+        self.reset_flag_after_logic_op();
     }
 
-    fn reg_xor_reg(&mut self, idxA: usize, idxB: usize) {
-        self.v_reg[idxA] ^= self.v_reg[idxB];
+    fn reg_xor_reg(&mut self, idx_a: usize, idx_b: usize) {
+        self.v_reg[idx_a] ^= self.v_reg[idx_b];
+        self.reset_flag_after_logic_op();
     }
 
-    fn add_reg_to_reg(&mut self, idxA: usize, idxB: usize) {
-        let (sum, carry) = self.v_reg[idxA].overflowing_add(self.v_reg[idxB]);
+    fn reset_flag_after_logic_op(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.v_reg[FLAG_REG] = 0;
+        }
+    }
 
-        self.v_reg[idxA] = sum;
+    fn add_reg_to_reg(&mut self, idx_a: usize, idx_b: usize) {
+        let (sum, carry) = self.v_reg[idx_a].overflowing_add(self.v_reg[idx_b]);
+
+        self.v_reg[idx_a] = sum;
         self.v_reg[FLAG_REG] = if carry {1} else {0};
     }
 
-    fn sub_reg_to_reg(&mut self, idxA: usize, idxB: usize) {
-        let (difference, borrow) = self.v_reg[idxA].overflowing_sub(self.v_reg[idxB]);
+    fn sub_reg_to_reg(&mut self, idx_a: usize, idx_b: usize) {
+        let (difference, borrow) = self.v_reg[idx_a].overflowing_sub(self.v_reg[idx_b]);
 
-        self.v_reg[idxA] = difference;
+        self.v_reg[idx_a] = difference;
         self.v_reg[FLAG_REG] = if borrow {0} else {1};
     }
 
-    fn single_right_shift(&mut self, idx: usize) {
+    fn single_right_shift(&mut self, idx: usize, idx_src: usize) {
+        if self.quirks.shift_uses_vy {
+            self.v_reg[idx] = self.v_reg[idx_src];
+        }
         let dropped_bit = self.v_reg[idx] & 1;
         self.v_reg[idx] >>= 1;
         self.v_reg[FLAG_REG] = dropped_bit;
     }
 
-    fn opposite_sub_reg_to_reg(&mut self, idxA: usize, idxB: usize) {
-        let (difference, borrow) = self.v_reg[idxB].overflowing_sub(self.v_reg[idxA]);
+    fn opposite_sub_reg_to_reg(&mut self, idx_a: usize, idx_b: usize) {
+        let (difference, borrow) = self.v_reg[idx_b].overflowing_sub(self.v_reg[idx_a]);
 
-        self.v_reg[idxA] = difference;
+        self.v_reg[idx_a] = difference;
         self.v_reg[FLAG_REG] = if borrow {0} else {1};
     }
 
-    fn single_left_shift(&mut self, idx: usize) {
+    fn single_left_shift(&mut self, idx: usize, idx_src: usize) {
+        if self.quirks.shift_uses_vy {
+            self.v_reg[idx] = self.v_reg[idx_src];
+        }
         let dropped_bit = (self.v_reg[idx] >> 7) & 1;
         self.v_reg[idx] <<= 1;
         self.v_reg[FLAG_REG] = dropped_bit;
     }
 
-    fn skip_next_if_reg_not_equals_reg(&mut self, idxA: usize, idxB: usize) {
-        if self.v_reg[idxA] == self.v_reg[idxB] {
-            self.pc += 2;
+    fn skip_next_if_reg_not_equals_reg(&mut self, idx_a: usize, idx_b: usize) {
+        if self.v_reg[idx_a] != self.v_reg[idx_b] {
+            self.skip_next();
         }
     }
 
-    fn assign_addr_to_ram_pointer(&mut self, op: u16) {
-        let nnn = op & 0xFFF;
+    fn assign_addr_to_ram_pointer(&mut self, nnn: u16) {
         self.i_reg = nnn;
     }
 
+    /// Sets I with the 16 bits address following the opcode
+    fn long_assign_addr_to_ram_pointer(&mut self) -> Result<(), EmulatorError> {
+        let higher_byte: u16 = self.read_ram(self.pc as usize)? as u16;
+        let lower_byte: u16 = self.read_ram(self.pc as usize + 1)? as u16;
+        self.i_reg = (higher_byte << 8) | lower_byte;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    fn load_audio_pattern(&mut self) -> Result<(), EmulatorError> {
+        let i: usize = self.i_reg as usize;
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_ram(i + offset)?;
+        }
+        self.audio.set_pattern(pattern);
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, idx: usize) {
+        self.audio.set_pitch(self.v_reg[idx]);
+    }
+
+    fn select_planes(&mut self, mask: u8) {
+        self.screen.select_planes(mask);
+    }
+
+    /// Sets the pc with the nnn address plus V0,
+    /// or plus VX (X being the highest nibble of nnn) with the BXNN quirk.
+    fn jump_with_offset(&mut self, nnn: u16) {
+        let idx: usize = if self.quirks.jump_uses_vx { (nnn >> 8) as usize } else { 0 };
+        self.pc = nnn + self.v_reg[idx] as u16;
+    }
+
     fn assign_random_to_reg(&mut self, idx: usize, val: u8) {
-        let rand: u8 = random();
+        let rand: u8 = self.rng.next_byte();
         self.v_reg[idx] = rand & val;
     }
 
-    fn draw_sprite(&mut self, x_coord: u16, y_coord: u16, num_rows: u16) {
+    /// Draws num_rows rows of 8 pixels, or a 16x16 sprite
+    /// (two bytes per row) when num_rows is 0.
+    /// Every selected plane gets its own sprite, stored one after the other.
+    fn draw_sprite(&mut self, x_coord: u16, y_coord: u16, num_rows: u16) -> Result<(), EmulatorError> {
+        let (num_rows, row_bytes): (usize, usize) = if num_rows == 0 { (16, 2) } else { (num_rows as usize, 1) };
+        let width: usize = self.screen.width();
+        let height: usize = self.screen.height();
+
         // Keep track if any pixels were flipped
         let mut flipped: bool = false;
 
-        // Iterate over each row of the sprite
-        for y_line in 0..num_rows {
+        // The starting position always wraps around the screen
+        let x_start: usize = x_coord as usize % width;
+        let y_start: usize = y_coord as usize % height;
 
-            // Determine which memory address the row's data is stored
-            let addr: u16 = self.i_reg + y_line;
-            let pixels: u8 = self.ram[addr as usize];
-            
-            // Iterate over each column in our row
-            for x_line in 0..8 {
+        let mut addr: usize = self.i_reg as usize;
 
-                // Use a mask to fetch current pixel's bit.
-                // Only flip if a 1.
-                if (pixels & (0b1000_0000 >> x_line)) != 0 {
-
-                    // Sprites should wrap around screen, so apply module.
-                    let x: usize = (x_coord + x_line) as usize % SCREEN_WIDTH;
-                    let y: usize = (y_coord + y_line) as usize % SCREEN_HEIGHT;
+        for plane_idx in 0..NUM_PLANES {
+            let plane: u8 = 1 << plane_idx;
+            if self.screen.planes() & plane == 0 {
+                continue;
+            }
 
-                    // Get the pixel's index for the ID screen array
-                    let idx: usize = x + SCREEN_WIDTH * y;
+            // Iterate over each row of the sprite
+            for y_line in 0..num_rows {
 
-                    // Check if we're about to flip the pixel and set
-                    flipped |= self.screen[idx];
-                    self.screen[idx] = true;
+                // Fetch the row's data, and move to the next one
+                let mut pixels: u16 = 0;
+                for _ in 0..row_bytes {
+                    pixels = (pixels << 8) | self.read_ram(addr)? as u16;
+                    addr += 1;
+                }
+                let row_width: usize = row_bytes * 8;
+
+                // Iterate over each column in our row
+                for x_line in 0..row_width {
+
+                    // Use a mask to fetch current pixel's bit.
+                    // Only flip if a 1.
+                    if (pixels & (1 << (row_width - 1 - x_line))) != 0 {
+
+                        let mut x: usize = x_start + x_line;
+                        let mut y: usize = y_start + y_line;
+
+                        // Pixels past the edges are either clipped, or
+                        // wrapped around the screen applying module.
+                        if x >= width || y >= height {
+                            if self.quirks.clip_sprites {
+                                continue;
+                            }
+                            x %= width;
+                            y %= height;
+                        }
+
+                        // Check if we're about to flip the pixel and flip it
+                        flipped |= self.screen.flip(x, y, plane);
+                    }
                 }
             }
         }
@@ -220,21 +463,22 @@ impl Emulator {
         // Populate VF register
         self.v_reg[FLAG_REG] = if flipped {1} else {0};
 
+        Ok(())
     }
 
     fn skip_if_key_pressed(&mut self, idx: usize) {
-        let vx: u8 = self.v_reg[idx];
+        let vx: u8 = self.v_reg[idx] & 0xF;
         let key: bool = self.keys[vx as usize];
         if key {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
     fn skip_if_key_not_pressed(&mut self, idx: usize) {
-        let vx: u8 = self.v_reg[idx];
+        let vx: u8 = self.v_reg[idx] & 0xF;
         let key: bool = self.keys[vx as usize];
         if !key {
-            self.pc += 2;
+            self.skip_next();
         }
     }
 
@@ -255,7 +499,7 @@ impl Emulator {
 
         if !pressed {
             // Redo opcode
-            self.pc -= 2;
+            self.pc = self.pc.wrapping_sub(2);
         }
     }
 
@@ -269,207 +513,875 @@ impl Emulator {
 
     fn increment_ram_pointer_by_reg(&mut self, idx: usize) {
         let vx: u16 = self.v_reg[idx] as u16;
-        self.i_reg += self.i_reg.wrapping_add(vx);
+        self.i_reg = self.i_reg.wrapping_add(vx);
     }
 
+    /// Only the lowest digit of VX counts
     fn set_ram_pointer_to_font_addr(&mut self, idx: usize) {
-        let c: u16 = self.v_reg[idx] as u16;
+        let c: u16 = (self.v_reg[idx] & 0xF) as u16;
         self.i_reg = c * 5;
     }
 
+    fn set_ram_pointer_to_big_font_addr(&mut self, idx: usize) {
+        let c: u16 = (self.v_reg[idx] & 0xF) as u16;
+        self.i_reg = BIG_FONTSET_ADDR as u16 + c * 10;
+    }
+
     /// BCD: Binary-Coded Decimal
-    fn set_ram_pointer_to_bcd_of_reg(&mut self, idx: usize) {
-        let vx: f32 = self.v_reg[idx] as f32;
+    fn set_ram_pointer_to_bcd_of_reg(&mut self, idx: usize) -> Result<(), EmulatorError> {
+        let vx: u8 = self.v_reg[idx];
 
-        let hundreds: u8 = (vx / 100.0).floor() as u8;
-        let tens: u8 = ((vx / 10.0) % 10.0).floor() as u8;
-        let ones: u8 = (vx % 1.0).floor() as u8;
+        let hundreds: u8 = vx / 100;
+        let tens: u8 = (vx / 10) % 10;
+        let ones: u8 = vx % 10;
 
-        self.ram[self.i_reg as usize] = hundreds;
-        self.ram[(self.i_reg + 1) as usize] = tens;
-        self.ram[(self.i_reg + 2) as usize] = ones;
+        let i: usize = self.i_reg as usize;
+        self.write_ram(i, hundreds)?;
+        self.write_ram(i + 1, tens)?;
+        self.write_ram(i + 2, ones)
     }
 
-    fn store_regs_in_ram(&mut self, idx: usize) {
+    fn store_regs_in_ram(&mut self, idx: usize) -> Result<(), EmulatorError> {
         let i: usize = self.i_reg as usize;
 
         for x in 0..=idx {
-            self.ram[i + x] = self.v_reg[x];
+            self.write_ram(i + x, self.v_reg[x])?;
         }
+        self.increment_ram_pointer_after_load_store(idx);
+        Ok(())
     }
 
-    fn load_regs_from_ram(&mut self, idx: usize) {
+    fn load_regs_from_ram(&mut self, idx: usize) -> Result<(), EmulatorError> {
         let i: usize = self.i_reg as usize;
 
         for x in 0..=idx {
-            self.v_reg[x] = self.ram[i + x];
+            self.v_reg[x] = self.read_ram(i + x)?;
         }
+        self.increment_ram_pointer_after_load_store(idx);
+        Ok(())
     }
 
-    /// NOP: do nothing
-    /// CLS: clear screen
-    /// RET: return from subroutine
-    /// JMP: jump to the given address
-    /// CALL: call a new subroutine
-    fn execute(&mut self, op: u16) {
-        let hex_digit1: u16 = (op & 0xF000) >> 12;
-        let hex_digit2: u16 = (op & 0xF000) >> 8;
-        let hex_digit3: u16 = (op & 0xF000) >> 4;
-        let hex_digit4: u16 = op & 0xF000;
+    fn store_regs_in_flags(&mut self, idx: usize) {
+        self.rpl[..=idx].copy_from_slice(&self.v_reg[..=idx]);
+    }
 
-        match (hex_digit1, hex_digit2, hex_digit3, hex_digit4) {
-            // NOP
-            (0, 0, 0, 0) => return,
+    fn load_regs_from_flags(&mut self, idx: usize) {
+        self.v_reg[..=idx].copy_from_slice(&self.rpl[..=idx]);
+    }
 
-            // CLS
-            (0, 0, 0xE, 0) => self.clear_screen(),
+    fn increment_ram_pointer_after_load_store(&mut self, idx: usize) {
+        let increment: u16 = match self.quirks.load_store_increment {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::X => idx as u16,
+            IndexIncrement::XPlusOne => idx as u16 + 1,
+        };
+        self.i_reg = self.i_reg.wrapping_add(increment);
+    }
 
-            // RET
-            (0, 0, 0xE, 0xE) => self.return_from_subroutine(),
+    /// Runs the already decoded instruction.
+    /// The operands come straight from the opcode nibbles, see `operations::decode`.
+    fn execute(&mut self, instruction: Instruction) -> Result<(), EmulatorError> {
+        match instruction {
+            Instruction::Nop => (),
+            Instruction::Sys { .. } => (),
+            Instruction::ClearScreen => self.clear_screen(),
+            Instruction::Return => self.return_from_subroutine()?,
+            Instruction::ScrollDown { n } => self.scroll_down(n),
+            Instruction::ScrollUp { n } => self.scroll_up(n),
+            Instruction::ScrollRight => self.scroll_right(),
+            Instruction::ScrollLeft => self.scroll_left(),
+            Instruction::Exit => self.exit(),
+            Instruction::LowRes => self.set_hires(false),
+            Instruction::HighRes => self.set_hires(true),
+            Instruction::Jump { nnn } => self.jump(nnn),
+            Instruction::Call { nnn } => self.call(nnn)?,
+            Instruction::SkipIfRegEqualsVal { x, nn } => self.skip_next_if_reg_equals_val(x as usize, nn),
+            Instruction::SkipIfRegNotEqualsVal { x, nn } => self.skip_next_if_reg_not_equals_val(x as usize, nn),
+            Instruction::SkipIfRegEqualsReg { x, y } => self.skip_next_if_reg_equals_reg(x as usize, y as usize),
+            Instruction::StoreRegRangeInRam { x, y } => self.store_reg_range_in_ram(x as usize, y as usize)?,
+            Instruction::LoadRegRangeFromRam { x, y } => self.load_reg_range_from_ram(x as usize, y as usize)?,
+            Instruction::AssignValToReg { x, nn } => self.assign_val_to_reg(x as usize, nn),
+            Instruction::AddValToReg { x, nn } => self.add_val_to_reg(x as usize, nn),
+            Instruction::AssignRegToReg { x, y } => self.assign_reg_to_reg(x as usize, y as usize),
+            Instruction::RegOrReg { x, y } => self.reg_or_reg(x as usize, y as usize),
+            Instruction::RegAndReg { x, y } => self.reg_and_reg(x as usize, y as usize),
+            Instruction::RegXorReg { x, y } => self.reg_xor_reg(x as usize, y as usize),
+            Instruction::AddRegToReg { x, y } => self.add_reg_to_reg(x as usize, y as usize),
+            Instruction::SubRegToReg { x, y } => self.sub_reg_to_reg(x as usize, y as usize),
+            Instruction::RightShift { x, y } => self.single_right_shift(x as usize, y as usize),
+            Instruction::OppositeSubRegToReg { x, y } => self.opposite_sub_reg_to_reg(x as usize, y as usize),
+            Instruction::LeftShift { x, y } => self.single_left_shift(x as usize, y as usize),
+            Instruction::SkipIfRegNotEqualsReg { x, y } => self.skip_next_if_reg_not_equals_reg(x as usize, y as usize),
+            Instruction::AssignAddrToRamPointer { nnn } => self.assign_addr_to_ram_pointer(nnn),
+            Instruction::JumpWithOffset { nnn } => self.jump_with_offset(nnn),
+            Instruction::AssignRandomToReg { x, nn } => self.assign_random_to_reg(x as usize, nn),
+            Instruction::DrawSprite { x, y, n } => {
+                let x_coord: u16 = self.v_reg[x as usize] as u16;
+                let y_coord: u16 = self.v_reg[y as usize] as u16;
+                self.draw_sprite(x_coord, y_coord, n as u16)?
+            }
+            Instruction::SkipIfKeyPressed { x } => self.skip_if_key_pressed(x as usize),
+            Instruction::SkipIfKeyNotPressed { x } => self.skip_if_key_not_pressed(x as usize),
+            Instruction::LongAssignAddrToRamPointer => self.long_assign_addr_to_ram_pointer()?,
+            Instruction::SelectPlanes { n } => self.select_planes(n),
+            Instruction::LoadAudioPattern => self.load_audio_pattern()?,
+            Instruction::AssignDelayTimerToReg { x } => self.assign_delay_timer_to_reg(x as usize),
+            Instruction::WaitForKeyPress { x } => self.wait_for_key_press(x as usize),
+            Instruction::AssignRegToDelayTimer { x } => self.assign_reg_to_delay_timer(x as usize),
+            Instruction::AssignRegToSoundTimer { x } => self.assign_reg_to_sound_timer(x as usize),
+            Instruction::IncrementRamPointerByReg { x } => self.increment_ram_pointer_by_reg(x as usize),
+            Instruction::SetRamPointerToFontAddr { x } => self.set_ram_pointer_to_font_addr(x as usize),
+            Instruction::SetRamPointerToBigFontAddr { x } => self.set_ram_pointer_to_big_font_addr(x as usize),
+            Instruction::SetPitch { x } => self.set_pitch(x as usize),
+            Instruction::SetRamPointerToBcdOfReg { x } => self.set_ram_pointer_to_bcd_of_reg(x as usize)?,
+            Instruction::StoreRegsInRam { x } => self.store_regs_in_ram(x as usize)?,
+            Instruction::LoadRegsFromRam { x } => self.load_regs_from_ram(x as usize)?,
+            Instruction::StoreRegsInFlags { x } => self.store_regs_in_flags(x as usize),
+            Instruction::LoadRegsFromFlags { x } => self.load_regs_from_flags(x as usize),
+        }
 
-            // JMP NNN
-            (1, _, _, _) => self.jump(op),
+        Ok(())
+    }
 
-            // CALL NNN
-            (2, _, _, _) => self.call(op),
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
 
-            // SKIP NEXT IF REG == VAL
-            (3, _, _, _) => self.skip_next_if_reg_equals_val(hex_digit2 as usize, (op & 0xFF) as u8),
+    /// Takes effect from the next instruction on
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
 
-            // SKIP NEXT IF REG != VAL
-            (4, _, _, _) => self.skip_next_if_reg_not_equals_val(hex_digit2 as usize, (op & 0xFF) as u8),
+    /// Serializes the whole machine, RNG and quirks included, in the
+    /// versioned format described in the state module.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        writer.u16(self.pc);
+        writer.bytes(&self.ram);
+        self.screen.save(&mut writer);
+        writer.bytes(&self.v_reg);
+        writer.u16(self.i_reg);
+        writer.u16(self.sp);
+        self.stack.iter().for_each(|&addr| writer.u16(addr));
+        self.keys.iter().for_each(|&key| writer.bool(key));
+        writer.u8(self.dt);
+        writer.u8(self.st);
+        writer.bytes(&self.rpl);
+        writer.bool(self.halted);
+        self.audio.save(&mut writer);
+        let rng_state: Vec<u8> = self.rng.save();
+        writer.u16(rng_state.len() as u16);
+        writer.bytes(&rng_state);
+        self.quirks.save(&mut writer);
+
+        writer.finish()
+    }
 
-            // SKIP NEXT IF REG == REG
-            (5, _, _, 0) => self.skip_next_if_reg_equals_reg(hex_digit2 as usize, hex_digit3 as usize),
+    /// Restores a save_state snapshot. Nothing changes unless the
+    /// whole snapshot is valid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader::new(data)?;
+
+        let pc: u16 = reader.u16()?;
+        let ram = reader.array::<RAM_SIZE>()?;
+        let screen = Screen::restore(&mut reader)?;
+        let v_reg = reader.array::<NUM_VREGS>()?;
+        let i_reg: u16 = reader.u16()?;
+        let sp: u16 = reader.u16()?;
+        let mut stack = [0; STACK_SIZE];
+        for addr in stack.iter_mut() {
+            *addr = reader.u16()?;
+        }
+        let mut keys = [false; NUM_KEYS];
+        for key in keys.iter_mut() {
+            *key = reader.bool("keys")?;
+        }
+        let dt: u8 = reader.u8()?;
+        let st: u8 = reader.u8()?;
+        let rpl = reader.array::<NUM_VREGS>()?;
+        let halted: bool = reader.bool("halted")?;
+        let audio = Audio::restore(&mut reader)?;
+        let rng_state_len: u16 = reader.u16()?;
+        let rng_state: &[u8] = reader.bytes(rng_state_len as usize)?;
+        let quirks = Quirks::restore(&mut reader)?;
+        reader.finish()?;
+
+        if sp as usize > STACK_SIZE {
+            return Err(StateError::InvalidValue { field: "sp" });
+        }
+        // Last check, as a valid state replaces the RNG one
+        if !self.rng.restore(rng_state) {
+            return Err(StateError::InvalidValue { field: "rng" });
+        }
 
-            // VX = NN
-            (6, _,_, _) => self.assign_val_to_reg(hex_digit2 as usize, (op & 0xFF) as u8),
+        self.pc = pc;
+        self.ram = ram;
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+        if let Some(recompiler) = self.recompiler.as_mut() {
+            recompiler.clear();
+        }
+        if let Some(timing) = self.timing.as_mut() {
+            timing.clear();
+        }
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.state_replaced();
+        }
+        self.screen = screen;
+        self.v_reg = v_reg;
+        self.i_reg = i_reg;
+        self.sp = sp;
+        self.stack = stack;
+        self.keys = keys;
+        self.dt = dt;
+        self.st = st;
+        self.rpl = rpl;
+        self.halted = halted;
+        self.audio = audio;
+        self.quirks = quirks;
+        Ok(())
+    }
 
-            // VX += NN
-            (7, _, _, _) => self.add_val_to_reg(hex_digit2 as usize, (op & 0xFF) as u8),
+    /// Replaces the CXNN random numbers source
+    pub fn set_random_source(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
 
-            // VX = VY
-            (8, _, _, 0) => self.assign_reg_to_reg(hex_digit2 as usize, hex_digit3 as usize),
+    /// Starts keeping a snapshot every interval frames (see run_frame),
+    /// up to capacity of them. Any previous history is dropped.
+    pub fn enable_rewind(&mut self, capacity: usize, interval: usize) {
+        self.rewind = Some(RewindBuffer::new(capacity, interval));
+    }
 
-            // VX |= VY
-            (8, _, _, 1) => self.reg_or_reg(hex_digit2 as usize, hex_digit3 as usize),
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
 
-            // VX &= VY
-            (8, _, _, 2) => self.reg_and_reg(hex_digit2 as usize, hex_digit3 as usize),
+    /// Number of snapshots rewind can go back to
+    pub fn rewind_len(&self) -> usize {
+        self.rewind.as_ref().map_or(0, |rewind| rewind.len())
+    }
 
-            // VX ^= VY
-            (8, _, _, 3) => self.reg_xor_reg(hex_digit2 as usize, hex_digit3 as usize),
+    /// Goes back steps snapshots, dropping the newer ones: with a snapshot
+    /// every frame, rewind(1) undoes the last frame. Going further than the
+    /// oldest snapshot stops there.
+    /// Returns false, changing nothing, when there's nothing to go back to.
+    pub fn rewind(&mut self, steps: usize) -> bool {
+        let snapshot = match self.rewind.as_mut().and_then(|rewind| rewind.pop(steps)) {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        // The snapshots are our own save states, always valid
+        self.load_state(&snapshot).is_ok()
+    }
 
-            // VX += VY
-            (8, _, _, 4) => self.add_reg_to_reg(hex_digit2 as usize, hex_digit3 as usize),
+    pub fn reset(&mut self) {
+        self.pc = START_ADDR;
+        self.ram = [0; RAM_SIZE];
+        self.screen = Screen::new();
+        self.v_reg = [0; NUM_VREGS];
+        self.i_reg = 0;
+        self.sp = 0;
+        self.stack=  [0; STACK_SIZE];
+        self.keys = [false; NUM_KEYS];
+        self.dt = 0;
+        self.st = 0;
+        // The RPL flags are kept, like the HP-48 did
+        self.halted = false;
+        self.audio = Audio::new();
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.clear();
+        }
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+        if let Some(recompiler) = self.recompiler.as_mut() {
+            recompiler.clear();
+        }
+        if let Some(timing) = self.timing.as_mut() {
+            timing.clear();
+        }
+        self.load_fonts();
+    }
 
-            // VX -= VY
-            (8, _, _, 5) => self.sub_reg_to_reg(hex_digit2 as usize, hex_digit3 as usize),
+    /// Keeps decoded instructions around instead of decoding them on every
+    /// tick, which runs loops a lot faster. Writes to RAM through FX55, FX33,
+    /// poke or load invalidate what they overwrite, so self-modifying
+    /// programs still run the same.
+    pub fn enable_decode_cache(&mut self) {
+        if self.cache.is_none() {
+            self.cache = Some(DecodeCache::new());
+        }
+    }
 
-            // VX >>= 1
-            (8, _, _, 6) => self.single_right_shift(hex_digit2 as usize),
+    pub fn disable_decode_cache(&mut self) {
+        self.cache = None;
+    }
 
-            // VX = VY - VX
-            (8, _, _, 7) => self.opposite_sub_reg_to_reg(hex_digit2 as usize, hex_digit3 as usize),
+    /// Makes run_frame translate straight-line runs of instructions into
+    /// chains of closures the first time it reaches them, and run those
+    /// instead of decoding every instruction. The results are the same as
+    /// with tick, which still interprets; so does run_frame while tracing.
+    pub fn enable_recompiler(&mut self) {
+        if self.recompiler.is_none() {
+            self.recompiler = Some(Recompiler::new());
+        }
+    }
 
-            // VX <<= 1
-            (8, _, _, 0xE) => self.single_left_shift(hex_digit2 as usize),
+    pub fn disable_recompiler(&mut self) {
+        self.recompiler = None;
+    }
 
-            // SKIP NEXT IF REG != REG
-            (9, _, _, 0) => self.skip_next_if_reg_not_equals_reg(hex_digit2 as usize, hex_digit3 as usize),
+    /// Makes run_frame charge every instruction what it costs on the COSMAC
+    /// VIP, see timing, and run as many as fit in a frame instead of
+    /// ticks_per_frame. Like on the VIP, DXYN waits for the vertical blank,
+    /// so at most one sprite gets drawn per frame.
+    pub fn enable_vip_timing(&mut self) {
+        if self.timing.is_none() {
+            self.timing = Some(VipTiming::new());
+        }
+    }
 
-            // I = NNN
-            (0xA, _, _, _) => self.assign_addr_to_ram_pointer(op),
+    pub fn disable_vip_timing(&mut self) {
+        self.timing = None;
+    }
 
-            // VX = rand & NN
-            (0xC, _, _, _) => self.assign_random_to_reg(hex_digit2 as usize, (op & 0xFF) as u8),
+    /// Records every instruction tick runs from now on, see Tracer
+    pub fn enable_trace(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
 
-            // DRAW
-            (0xD, _, _, _) => self.draw_sprite(hex_digit2, hex_digit3, hex_digit4),
+    /// Stops tracing, and hands the tracer back
+    pub fn disable_trace(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
 
-            // SKIP KEY PRESS
-            (0xE, _, 9, 0xE) => self.skip_if_key_pressed(hex_digit2 as usize),
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
 
-            // SKIP KEY RELEASE
-            (0xE, _, 0xA, 1) => self.skip_if_key_not_pressed(hex_digit2 as usize),
+    /// Runs a single instruction, unless the program exited.
+    /// On error the pc is left pointing to the faulty instruction.
+    pub fn tick(&mut self) -> Result<(), EmulatorError> {
+        if self.halted {
+            return Ok(());
+        }
 
-            // VX = DT
-            (0xF, _, 0, 7) => self.assign_delay_timer_to_reg(hex_digit2 as usize),
+        let pc: u16 = self.pc;
+        let (op, decoded) = self.fetch_decoded()?;
+        let before: Option<Registers> = self.tracer
+            .as_ref()
+            .filter(|tracer| tracer.wants(pc, op))
+            .map(|_| self.registers());
+
+        let instruction: Option<Instruction> = decoded.ok();
+        let result: Result<(), EmulatorError> = match instruction {
+            Some(instruction) => self.execute(instruction),
+            None => Err(EmulatorError::UnknownOpcode { pc, op }),
+        };
+        if result.is_err() {
+            self.pc = pc;
+        }
 
-            // WAIT KEY
-            (0xF, _, 0, 0xA) => self.wait_for_key_press(hex_digit2 as usize),
+        // Failed instructions are traced too, they're usually what the trace is for
+        if let Some(before) = before {
+            let entry = TraceEntry { pc, op, instruction, before, after: self.registers(), error: result.err() };
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.record(entry);
+            }
+        }
+        result
+    }
 
-            // DT = VX
-            (0xF, _, 1, 5) => self.assign_reg_to_delay_timer(hex_digit2 as usize),
+    fn registers(&self) -> Registers {
+        Registers { v: self.v_reg, i: self.i_reg, sp: self.sp, stack: self.stack, dt: self.dt, st: self.st }
+    }
 
-            // ST = VX
-            (0xF, _, 1, 8) => self.assign_reg_to_sound_timer(hex_digit2 as usize),
+    pub fn tick_timers(&mut self) {
+        if self.dt > 0 {
+            self.dt -= 1;
+        }
 
-            // I += VX
-            (0xF, _, 1, 0xE) => self.increment_ram_pointer_by_reg(hex_digit2 as usize),
+        if self.st > 0 {
+            self.st -= 1;
+        }
+    }
 
-            // I = FONT
-            (OxF, _, 2, 9) => self.set_ram_pointer_to_font_addr(hex_digit2 as usize),
+    /// Ticks until the instructions ran have used up the frame's cycles,
+    /// or until a DXYN that has to wait for the next vertical blank
+    fn run_timed(&mut self) -> Result<(), EmulatorError> {
+        let mut spent: usize = self.timing.as_ref().map_or(0, |timing| timing.overrun());
+        // The frame starts on a vertical blank, unless the last one ran over it
+        let mut at_vblank: bool = spent == 0;
+
+        let mut result = Ok(());
+        while spent < INTERPRETER_CYCLES_PER_FRAME && !self.halted {
+            let cost: usize = match self.peek() {
+                Some(Instruction::DrawSprite { .. }) if !at_vblank => break,
+                Some(instruction) => timing::cycles(instruction, &self.v_reg),
+                None => 0, // Whatever is wrong with it, tick reports it
+            };
+            result = self.tick();
+            if result.is_err() {
+                break;
+            }
+            spent += cost;
+            at_vblank = false;
+        }
 
-            // I = BCD of VX
-            (0xF, _, 3, 3) => self.set_ram_pointer_to_bcd_of_reg(hex_digit2 as usize),
+        if let Some(timing) = self.timing.as_mut() {
+            timing.end_frame(spent);
+        }
+        result
+    }
 
-            // STORE V0..VX INTO RAM
-            (0xF, _, 5, 5) => self.store_regs_in_ram(hex_digit2 as usize),
+    /// The instruction at the pc, without running it
+    fn peek(&self) -> Option<Instruction> {
+        let high: u8 = self.read_ram(self.pc as usize).ok()?;
+        let low: u8 = self.read_ram(self.pc as usize + 1).ok()?;
+        decode(u16::from_be_bytes([high, low])).ok()
+    }
 
-            // LOAD V0..VX FROM RAM
-            (0xF, _, 6, 5) =>self.load_regs_from_ram(hex_digit2 as usize),
+    /// Runs a 60 Hz frame: ticks_per_frame instructions, or a frame's worth
+    /// of machine cycles with VIP timing, then a timers tick.
+    /// With rewind enabled, the frame may end with a snapshot.
+    pub fn run_frame(&mut self, ticks_per_frame: usize) -> Result<(), EmulatorError> {
+        if self.timing.is_some() {
+            self.run_timed()?;
+        } else if self.recompiler.is_some() && self.tracer.is_none() {
+            self.run_compiled(ticks_per_frame)?;
+        } else {
+            for _ in 0..ticks_per_frame {
+                self.tick()?;
+            }
+        }
+        self.tick_timers();
 
-            (_, _, _ , _) => unimplemented!("Unimplemented opcode: {}", op),
+        if self.rewind.as_mut().is_some_and(|rewind| rewind.frame_done()) {
+            let snapshot = self.save_state();
+            if let Some(rewind) = self.rewind.as_mut() {
+                rewind.push(&snapshot);
+            }
         }
+        Ok(())
+    }
 
+    /// Generates the sound for the next out.len() samples, to be played
+    /// at sample_rate. Silent unless the sound timer is running.
+    pub fn fill_audio(&mut self, out: &mut [f32], sample_rate: u32) {
+        let playing: bool = self.is_sound_active();
+        self.audio.fill(out, sample_rate, playing);
     }
 
-    pub fn reset(&mut self) {
-        self.pc = START_ADDR;
-        self.ram = [0; RAM_SIZE];
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-        self.v_reg = [0; NUM_VREGS];
-        self.i_reg = 0;
-        self.sp = 0;
-        self.stack=  [0; STACK_SIZE];
-        self.keys = [false; NUM_KEYS];
-        self.dt = 0;
-        self.st = 0;
-        self.ram[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+    /// True while the sound timer is running, and the buzzer should sound
+    pub fn is_sound_active(&self) -> bool {
+        self.st > 0
     }
 
-    pub fn tick(&mut self) {
-        let op = self.fetch();
-        // Decode and execute
-        self.execute(op);
+    // Read-only views of the machine, for debuggers and tools
+
+    pub fn pc(&self) -> u16 {
+        self.pc
     }
 
-    pub fn tick_timers(&mut self) {
-        if self.dt > 0 {
-            self.dt -= 1;
-        }
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
 
-        if self.st > 0 {
-            // BEEP
-        }
-        self.st -= 1;
+    pub fn v_reg(&self) -> &[u8; NUM_VREGS] {
+        &self.v_reg
+    }
+
+    pub fn i_reg(&self) -> u16 {
+        self.i_reg
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    /// Every slot, the ones in use being stack()[..sp()]
+    pub fn stack(&self) -> &[u16; STACK_SIZE] {
+        &self.stack
+    }
+
+    pub fn keys(&self) -> &[bool; NUM_KEYS] {
+        &self.keys
+    }
+
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn st(&self) -> u8 {
+        self.st
     }
 
-    pub fn get_display(&self) -> &[bool] {
+    // Write access to the machine, for debuggers
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Only the low nibble of idx counts, like the X of an opcode
+    pub fn set_v_reg(&mut self, idx: usize, value: u8) {
+        self.v_reg[idx & 0xF] = value;
+    }
+
+    pub fn set_i_reg(&mut self, value: u16) {
+        self.i_reg = value;
+    }
+
+    /// sp is capped at STACK_SIZE
+    pub fn set_sp(&mut self, sp: u16) {
+        self.sp = sp.min(STACK_SIZE as u16);
+    }
+
+    pub fn set_dt(&mut self, value: u8) {
+        self.dt = value;
+    }
+
+    pub fn set_st(&mut self, value: u8) {
+        self.st = value;
+    }
+
+    /// Writes a single byte of RAM
+    pub fn poke(&mut self, addr: usize, value: u8) -> Result<(), EmulatorError> {
+        self.write_ram(addr, value)
+    }
+
+    /// The screen in its current resolution
+    pub fn get_display(&self) -> &Screen {
         &self.screen
     }
 
+    /// True once the program ran the exit instruction
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
     /// pressed = true => key has been pressed.
     /// pressed = false => key has been released.
+    /// Only the low nibble of idx counts, as with EX9E.
     pub fn keypress(&mut self, idx: usize, pressed: bool) {
-        self.keys[idx] = pressed;
+        self.keys[idx & 0xF] = pressed;
     }
 
-    pub fn load(&mut self, data: &[u8]) {
+    pub fn load(&mut self, data: &[u8]) -> Result<(), EmulatorError> {
         let start: usize = START_ADDR as usize;
         let end: usize = (START_ADDR as usize) + data.len();
+        if end > RAM_SIZE {
+            return Err(EmulatorError::RomTooLarge { size: data.len(), max: RAM_SIZE - start });
+        }
         self.ram[start..end].copy_from_slice(data);
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+        if let Some(recompiler) = self.recompiler.as_mut() {
+            recompiler.clear();
+        }
+        if let Some(timing) = self.timing.as_mut() {
+            timing.clear();
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_indexes_wrap_instead_of_panicking() {
+        let mut emulator = Emulator::default();
+        emulator.set_v_reg(0x12, 7);
+        emulator.keypress(usize::MAX, true);
+        assert_eq!(emulator.v_reg[2], 7);
+        assert!(emulator.keys[0xF]);
+    }
+
+    #[test]
+    fn emulator_can_move_to_another_thread() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Emulator>();
+    }
+
+    #[test]
+    fn unknown_opcode_is_reported_with_its_address() {
+        let mut emulator = Emulator::default();
+        emulator.load(&[0x60, 0x01, 0xFF, 0xFF]).unwrap();
+
+        assert_eq!(emulator.tick(), Ok(()));
+        assert_eq!(emulator.tick(), Err(EmulatorError::UnknownOpcode { pc: 0x202, op: 0xFFFF }));
+    }
+
+    #[test]
+    fn stack_errors_instead_of_panicking() {
+        let mut emulator = Emulator::default();
+        // 0x200: CALL 0x200, over and over
+        emulator.load(&[0x22, 0x00]).unwrap();
+        for _ in 0..STACK_SIZE {
+            assert_eq!(emulator.tick(), Ok(()));
+        }
+        assert_eq!(emulator.tick(), Err(EmulatorError::StackOverflow));
+
+        emulator.reset();
+        emulator.load(&[0x00, 0xEE]).unwrap();
+        assert_eq!(emulator.tick(), Err(EmulatorError::StackUnderflow));
+    }
+
+    #[test]
+    fn memory_errors_instead_of_panicking() {
+        let mut emulator = Emulator::default();
+        // I = 0xFFFF, store V0..VF
+        emulator.load(&[0xF0, 0x00, 0xFF, 0xFF, 0xFF, 0x55]).unwrap();
+        emulator.tick().unwrap();
+        assert_eq!(emulator.tick(), Err(EmulatorError::MemoryOutOfBounds { addr: RAM_SIZE }));
+
+        // The opcode's second byte is past the end of RAM
+        emulator.reset();
+        emulator.pc = 0xFFFF;
+        assert_eq!(emulator.tick(), Err(EmulatorError::MemoryOutOfBounds { addr: RAM_SIZE }));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn oversized_rom_is_rejected() {
+        let mut emulator = Emulator::default();
+        let max: usize = RAM_SIZE - START_ADDR as usize;
+
+        assert_eq!(emulator.load(&vec![0; max]), Ok(()));
+        assert_eq!(emulator.load(&vec![0; max + 1]), Err(EmulatorError::RomTooLarge { size: max + 1, max }));
+    }
+
+    #[test]
+    fn shift_quirk_selects_the_source_register() {
+        // V1 = 0b11, V2 = 0b100, V1 >>= 1 (8126)
+        let rom = [0x61, 0x03, 0x62, 0x04, 0x81, 0x26];
+
+        let mut emulator = Emulator::new(Quirks::cosmac_vip());
+        emulator.load(&rom).unwrap();
+        (0..3).for_each(|_| emulator.tick().unwrap());
+        assert_eq!((emulator.v_reg[1], emulator.v_reg[FLAG_REG]), (0b10, 0));
+
+        let mut emulator = Emulator::new(Quirks::super_chip());
+        emulator.load(&rom).unwrap();
+        (0..3).for_each(|_| emulator.tick().unwrap());
+        assert_eq!((emulator.v_reg[1], emulator.v_reg[FLAG_REG]), (0b1, 1));
+    }
+
+    #[test]
+    fn sprites_are_clipped_or_wrapped_at_the_edges() {
+        // V0 = 62, V1 = 0, I = font "0", draw 1 row (0xF0) at (V0, V1)
+        let rom = [0x60, 62, 0x61, 0x00, 0xA0, 0x00, 0xD0, 0x11];
+
+        let mut emulator = Emulator::new(Quirks::cosmac_vip());
+        emulator.load(&rom).unwrap();
+        (0..4).for_each(|_| emulator.tick().unwrap());
+        assert_eq!(&emulator.get_display().pixels()[62..64], &[1, 1]);
+        assert_eq!(emulator.get_display().pixels()[0], 0);
+
+        let mut emulator = Emulator::new(Quirks::xo_chip());
+        emulator.load(&rom).unwrap();
+        (0..4).for_each(|_| emulator.tick().unwrap());
+        assert_eq!(&emulator.get_display().pixels()[0..2], &[1, 1]);
+    }
+
+    #[test]
+    fn hires_mode_draws_16x16_sprites() {
+        // HIGH, I = 0x300, DRW V0, V0, 0 with a full 16x16 sprite at 0x300
+        let mut rom = vec![0x00, 0xFF, 0xA3, 0x00, 0xD0, 0x00];
+        rom.resize(0x100, 0);
+        rom.extend([0xFF; 32]);
+
+        let mut emulator = Emulator::default();
+        emulator.load(&rom).unwrap();
+        (0..3).for_each(|_| emulator.tick().unwrap());
+
+        let screen = emulator.get_display();
+        assert_eq!((screen.width(), screen.height()), (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT));
+        assert_eq!(screen.pixels().iter().filter(|&&p| p != 0).count(), 16 * 16);
+        assert_eq!(screen.pixels()[15 + HIRES_SCREEN_WIDTH * 15], 1);
+    }
+
+    #[test]
+    fn each_selected_plane_draws_its_own_sprite() {
+        // PLANE 3, I = 0x300, DRW V0, V0, 1 with 0x80 (plane 1) and 0xC0 (plane 2) at 0x300
+        let mut rom = vec![0xF3, 0x01, 0xA3, 0x00, 0xD0, 0x01];
+        rom.resize(0x100, 0);
+        rom.extend([0x80, 0xC0]);
+
+        let mut emulator = Emulator::new(Quirks::xo_chip());
+        emulator.load(&rom).unwrap();
+        (0..3).for_each(|_| emulator.tick().unwrap());
+
+        assert_eq!(&emulator.get_display().pixels()[..3], &[3, 2, 0]);
+    }
+
+    #[test]
+    fn skips_jump_over_the_whole_long_load() {
+        // SE V0, 0 ; I = 0x1234 (4 bytes) ; V1 = 1
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01];
+
+        let mut emulator = Emulator::new(Quirks::xo_chip());
+        emulator.load(&rom).unwrap();
+        (0..2).for_each(|_| emulator.tick().unwrap());
+
+        assert_eq!((emulator.i_reg, emulator.v_reg[1]), (0, 1));
+    }
+
+    #[test]
+    fn load_state_restores_a_save_state() {
+        // V0 = rand & 0xFF, CALL 0x200
+        let rom = [0xC0, 0xFF, 0x22, 0x00];
+        let mut emulator = Emulator::new(Quirks::super_chip());
+        emulator.load(&rom).unwrap();
+        emulator.tick().unwrap();
+        emulator.keypress(3, true);
+        let state = emulator.save_state();
+
+        (0..4).for_each(|_| emulator.tick().unwrap());
+        let after = emulator.save_state();
+
+        let mut restored = Emulator::default();
+        restored.load_state(&state).unwrap();
+        (0..4).for_each(|_| restored.tick().unwrap());
+        assert_eq!(restored.save_state(), after);
+    }
+
+    #[test]
+    fn load_state_rejects_bad_snapshots_untouched() {
+        let mut emulator = Emulator::default();
+        let state = emulator.save_state();
+        emulator.load(&[0x60, 0x01]).unwrap();
+        let before = emulator.save_state();
+
+        assert_eq!(emulator.load_state(b"nope"), Err(StateError::NotASaveState));
+        assert_eq!(emulator.load_state(&state[..state.len() - 1]), Err(StateError::Truncated));
+
+        let mut newer = state.clone();
+        newer[4] += 1;
+        assert_eq!(emulator.load_state(&newer), Err(StateError::UnsupportedVersion { version: newer[4] }));
+        assert_eq!(emulator.save_state(), before);
+    }
+
+    #[test]
+    fn seeded_emulators_draw_the_same_random_numbers() {
+        // V0 = rand & 0xFF, JMP 0x200
+        let rom = [0xC0, 0xFF, 0x12, 0x00];
+        let mut numbers: Vec<Vec<u8>> = Vec::new();
+
+        for seed in [42, 42, 43] {
+            let mut emulator = Emulator::with_seed(Quirks::default(), seed);
+            emulator.load(&rom).unwrap();
+            numbers.push((0..8).map(|_| {
+                emulator.tick().unwrap();
+                emulator.tick().unwrap();
+                emulator.v_reg[0]
+            }).collect());
+        }
+
+        assert_eq!(numbers[0], numbers[1]);
+        assert_ne!(numbers[0], numbers[2]);
+    }
+
+    #[test]
+    fn random_source_can_be_injected() {
+        struct Constant(u8);
+
+        impl RandomSource for Constant {
+            fn next_byte(&mut self) -> u8 { self.0 }
+            fn save(&self) -> Vec<u8> { vec![self.0] }
+            fn restore(&mut self, state: &[u8]) -> bool {
+                self.0 = state[0];
+                true
+            }
+        }
+
+        // V0 = rand & 0x0F
+        let mut emulator = Emulator::with_random_source(Quirks::default(), Box::new(Constant(0xAB)));
+        emulator.load(&[0xC0, 0x0F]).unwrap();
+        emulator.tick().unwrap();
+        assert_eq!(emulator.v_reg[0], 0x0B);
+    }
+
+    #[test]
+    fn rewind_goes_back_whole_frames() {
+        // V0 += 1, JMP 0x200
+        let mut emulator = Emulator::default();
+        emulator.load(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        emulator.enable_rewind(10, 1);
+        (0..5).for_each(|_| emulator.run_frame(2).unwrap());
+        assert_eq!((emulator.v_reg[0], emulator.rewind_len()), (5, 5));
+
+        // One step undoes frame 5, two more go back to the end of frame 2
+        assert!(emulator.rewind(1));
+        assert_eq!(emulator.v_reg[0], 4);
+        assert!(emulator.rewind(2));
+        assert_eq!(emulator.v_reg[0], 2);
+        assert!(emulator.rewind(99));
+        assert_eq!(emulator.v_reg[0], 1);
+        assert!(!emulator.rewind(1));
+    }
+
+    #[test]
+    fn decode_cache_sees_self_modifying_code() {
+        // 0x200: V0 = 0x72, V1 = 0x05, I = 0x210
+        // 0x206: CALL 0x210, then overwrite it with ADD V2, 5 and CALL it again
+        // 0x210: ADD V2, 1 and RET
+        let rom: [u8; 20] = [
+            0x60, 0x72, 0x61, 0x05, 0xA2, 0x10, 0x22, 0x10, 0xF1, 0x55,
+            0x22, 0x10, 0x12, 0x0C, 0x00, 0x00, 0x72, 0x01, 0x00, 0xEE,
+        ];
+
+        for cached in [false, true] {
+            let mut emulator = Emulator::default();
+            if cached {
+                emulator.enable_decode_cache();
+            }
+            emulator.load(&rom).unwrap();
+            (0..10).for_each(|_| emulator.tick().unwrap());
+            assert_eq!(emulator.v_reg[2], 6);
+
+            // Reloading replaces the cached instructions too
+            emulator.reset();
+            emulator.load(&[0x72, 0x03]).unwrap();
+            emulator.tick().unwrap();
+            assert_eq!(emulator.v_reg[2], 3);
+        }
+    }
+
+    #[test]
+    fn vip_timing_runs_frames_of_cycles() {
+        // ADD V0, 1 and JP 0x200, 105 cycles a round
+        let mut emulator = Emulator::default();
+        emulator.enable_vip_timing();
+        emulator.load(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        // ticks_per_frame is ignored, and the 27 cycles the last jump
+        // ran over are taken from the second frame
+        emulator.run_frame(1).unwrap();
+        assert_eq!(emulator.v_reg[0], 25);
+        emulator.run_frame(1).unwrap();
+        assert_eq!(emulator.v_reg[0], 50);
+
+        emulator.disable_vip_timing();
+        emulator.run_frame(10).unwrap();
+        assert_eq!(emulator.v_reg[0], 55);
+    }
+
+    #[test]
+    fn vip_timing_waits_for_the_vertical_blank_to_draw() {
+        // I = 0x000, three DRW V0, V0, 5, then JP 0x208
+        let mut emulator = Emulator::default();
+        emulator.enable_vip_timing();
+        emulator.load(&[0xA0, 0x00, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x08]).unwrap();
+
+        for pc in [0x202, 0x204, 0x206, 0x208] {
+            emulator.run_frame(1).unwrap();
+            assert_eq!(emulator.pc, pc);
+        }
+    }
+}
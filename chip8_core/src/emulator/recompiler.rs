@@ -0,0 +1,247 @@
+use super::{Emulator, RAM_SIZE};
+use crate::error::EmulatorError;
+use crate::operations::{decode, Instruction};
+use std::sync::Arc;
+
+/// Most instructions in a block
+const MAX_BLOCK_LEN: usize = 64;
+
+/// An instruction, compiled to a call straight to its handler
+type Run = Box<dyn Fn(&mut Emulator) -> Result<(), EmulatorError> + Send + Sync>;
+
+struct Op {
+    addr: u16,
+    next: u16, // Where the pc points while it runs, as after a fetch
+    run: Run,
+}
+
+/// A run of instructions, ending with the first one that may jump,
+/// skip, draw, wait for a key or write to memory
+struct Block {
+    ops: Vec<Op>,
+}
+
+/// Compiled blocks by start address. Blocks are compiled the first time
+/// the pc reaches them, and all of them are dropped whenever memory they
+/// were compiled from gets written to, which only self-modifying programs do.
+pub(super) struct Recompiler {
+    blocks: Vec<Option<Arc<Block>>>,
+    code: Vec<bool>, // Bytes some block was compiled from
+}
+
+impl Recompiler {
+
+    pub(super) fn new() -> Self {
+        Self {
+            blocks: vec![None; RAM_SIZE],
+            code: vec![false; RAM_SIZE],
+        }
+    }
+
+    pub(super) fn invalidate(&mut self, addr: usize) {
+        if self.code.get(addr) == Some(&true) {
+            self.clear();
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.blocks.fill(None);
+        self.code.fill(false);
+    }
+}
+
+impl Emulator {
+
+    /// Same as ticks_per_frame calls to tick, running compiled blocks
+    pub(super) fn run_compiled(&mut self, ticks_per_frame: usize) -> Result<(), EmulatorError> {
+        let mut ticks_left: usize = ticks_per_frame;
+
+        while ticks_left > 0 && !self.halted {
+            let Some(block) = self.block_at(self.pc) else {
+                // Whatever stopped the compiler, the interpreter reports it
+                self.tick()?;
+                ticks_left -= 1;
+                continue;
+            };
+
+            for op in block.ops.iter().take(ticks_left) {
+                self.pc = op.next;
+                if let Err(err) = (op.run)(self) {
+                    self.pc = op.addr;
+                    return Err(err);
+                }
+            }
+            ticks_left -= block.ops.len().min(ticks_left);
+        }
+
+        Ok(())
+    }
+
+    /// The block starting at addr, compiled if needed.
+    /// None if there's no valid instruction there.
+    fn block_at(&mut self, addr: u16) -> Option<Arc<Block>> {
+        let recompiler = self.recompiler.as_ref()?;
+        if let Some(block) = &recompiler.blocks[addr as usize] {
+            return Some(block.clone());
+        }
+
+        let mut ops: Vec<Op> = Vec::new();
+        let mut pc: u16 = addr;
+        while ops.len() < MAX_BLOCK_LEN {
+            let Ok(high) = self.read_ram(pc as usize) else { break };
+            let Ok(low) = self.read_ram(pc as usize + 1) else { break };
+            let Ok(instruction) = decode(u16::from_be_bytes([high, low])) else { break };
+
+            let next: u16 = pc.wrapping_add(2);
+            ops.push(Op { addr: pc, next, run: compile(instruction) });
+            pc = next;
+            if ends_block(instruction) {
+                break;
+            }
+        }
+        if ops.is_empty() {
+            return None;
+        }
+
+        let block: Arc<Block> = Arc::new(Block { ops });
+        let recompiler = self.recompiler.as_mut()?;
+        for op in &block.ops {
+            recompiler.code[op.addr as usize] = true;
+            recompiler.code[op.addr as usize + 1] = true;
+        }
+        recompiler.blocks[addr as usize] = Some(block.clone());
+        Some(block)
+    }
+}
+
+fn ends_block(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Return
+            | Instruction::Exit
+            | Instruction::Jump { .. }
+            | Instruction::Call { .. }
+            | Instruction::JumpWithOffset { .. }
+            | Instruction::SkipIfRegEqualsVal { .. }
+            | Instruction::SkipIfRegNotEqualsVal { .. }
+            | Instruction::SkipIfRegEqualsReg { .. }
+            | Instruction::SkipIfRegNotEqualsReg { .. }
+            | Instruction::SkipIfKeyPressed { .. }
+            | Instruction::SkipIfKeyNotPressed { .. }
+            | Instruction::DrawSprite { .. }
+            | Instruction::WaitForKeyPress { .. }
+            // Reads its address from the next word, moving the pc past it
+            | Instruction::LongAssignAddrToRamPointer
+            // Writes to memory, maybe to a compiled block
+            | Instruction::StoreRegRangeInRam { .. }
+            | Instruction::StoreRegsInRam { .. }
+            | Instruction::SetRamPointerToBcdOfReg { .. }
+    )
+}
+
+/// The most common straight-line instructions call their handler directly,
+/// the others go through execute
+fn compile(instruction: Instruction) -> Run {
+    match instruction {
+        Instruction::AssignValToReg { x, nn } => Box::new(move |emu| {
+            emu.assign_val_to_reg(x as usize, nn);
+            Ok(())
+        }),
+        Instruction::AddValToReg { x, nn } => Box::new(move |emu| {
+            emu.add_val_to_reg(x as usize, nn);
+            Ok(())
+        }),
+        Instruction::AssignRegToReg { x, y } => Box::new(move |emu| {
+            emu.assign_reg_to_reg(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::RegOrReg { x, y } => Box::new(move |emu| {
+            emu.reg_or_reg(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::RegAndReg { x, y } => Box::new(move |emu| {
+            emu.reg_and_reg(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::RegXorReg { x, y } => Box::new(move |emu| {
+            emu.reg_xor_reg(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::AddRegToReg { x, y } => Box::new(move |emu| {
+            emu.add_reg_to_reg(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::SubRegToReg { x, y } => Box::new(move |emu| {
+            emu.sub_reg_to_reg(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::OppositeSubRegToReg { x, y } => Box::new(move |emu| {
+            emu.opposite_sub_reg_to_reg(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::RightShift { x, y } => Box::new(move |emu| {
+            emu.single_right_shift(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::LeftShift { x, y } => Box::new(move |emu| {
+            emu.single_left_shift(x as usize, y as usize);
+            Ok(())
+        }),
+        Instruction::AssignAddrToRamPointer { nnn } => Box::new(move |emu| {
+            emu.assign_addr_to_ram_pointer(nnn);
+            Ok(())
+        }),
+        Instruction::IncrementRamPointerByReg { x } => Box::new(move |emu| {
+            emu.increment_ram_pointer_by_reg(x as usize);
+            Ok(())
+        }),
+        Instruction::AssignDelayTimerToReg { x } => Box::new(move |emu| {
+            emu.assign_delay_timer_to_reg(x as usize);
+            Ok(())
+        }),
+        Instruction::AssignRandomToReg { x, nn } => Box::new(move |emu| {
+            emu.assign_random_to_reg(x as usize, nn);
+            Ok(())
+        }),
+        Instruction::LoadRegsFromRam { x } => Box::new(move |emu| emu.load_regs_from_ram(x as usize)),
+        _ => Box::new(move |emu| emu.execute(instruction)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(rom: &[u8], ticks: usize, compiled: bool) -> Emulator {
+        let mut emulator = Emulator::with_seed(Default::default(), 0);
+        if compiled {
+            emulator.enable_recompiler();
+        }
+        emulator.load(rom).unwrap();
+        emulator.run_frame(ticks).unwrap();
+        emulator
+    }
+
+    #[test]
+    fn blocks_stop_mid_way_when_the_frame_ends() {
+        // V0 += 1 three times, then JP 0x200
+        let rom: [u8; 8] = [0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x12, 0x00];
+        for ticks in [1, 4, 6, 11] {
+            let interpreted = run(&rom, ticks, false);
+            let compiled = run(&rom, ticks, true);
+            assert_eq!((compiled.pc, compiled.v_reg[0]), (interpreted.pc, interpreted.v_reg[0]));
+        }
+    }
+
+    #[test]
+    fn writes_to_compiled_code_recompile_it() {
+        // 0x200: V0 = 0x72, V1 = 0x05, I = 0x210
+        // 0x206: CALL 0x210, then overwrite it with ADD V2, 5 and CALL it again
+        // 0x210: ADD V2, 1 and RET
+        let rom: [u8; 20] = [
+            0x60, 0x72, 0x61, 0x05, 0xA2, 0x10, 0x22, 0x10, 0xF1, 0x55,
+            0x22, 0x10, 0x12, 0x0C, 0x00, 0x00, 0x72, 0x01, 0x00, 0xEE,
+        ];
+        assert_eq!(run(&rom, 10, true).v_reg[2], 6);
+    }
+}
@@ -0,0 +1,37 @@
+use std::fmt;
+
+/// Everything that can go wrong while loading or running a ROM.
+/// A misbehaving ROM reports one of these instead of panicking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// The opcode at pc doesn't decode to any instruction
+    UnknownOpcode { pc: u16, op: u16 },
+    /// CALL with every stack slot already in use
+    StackOverflow,
+    /// RET with an empty stack
+    StackUnderflow,
+    /// Read or write past the end of RAM
+    MemoryOutOfBounds { addr: usize },
+    /// The ROM doesn't fit between START_ADDR and the end of RAM
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::UnknownOpcode { pc, op } => {
+                write!(f, "unknown opcode {:#06X} at {:#05X}", op, pc)
+            }
+            EmulatorError::StackOverflow => write!(f, "stack overflow"),
+            EmulatorError::StackUnderflow => write!(f, "stack underflow"),
+            EmulatorError::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:#X}", addr)
+            }
+            EmulatorError::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes, but only {} bytes fit in memory", size, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
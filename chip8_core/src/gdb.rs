@@ -0,0 +1,572 @@
+use crate::debugger::{Access, Debugger, StopReason};
+use crate::emulator::{Emulator, NUM_VREGS, RAM_SIZE};
+use crate::error::EmulatorError;
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Instructions run between checks for a Ctrl-C from gdb
+const RUN_CHUNK: usize = 1000;
+
+/// Longest packet data we send or accept, as advertised in qSupported
+const PACKET_SIZE: usize = 0x1000;
+
+/// Register numbers, as used by the p and P packets.
+/// V0 to VF are 0 to 15.
+const REG_I: usize = NUM_VREGS;
+const REG_PC: usize = NUM_VREGS + 1;
+const REG_SP: usize = NUM_VREGS + 2;
+const REG_DT: usize = NUM_VREGS + 3;
+const REG_ST: usize = NUM_VREGS + 4;
+const NUM_REGS: usize = NUM_VREGS + 5;
+
+const ERROR_REPLY: &str = "E01";
+
+/// GDB remote serial protocol server, debugging the wrapped emulator.
+///
+/// The g packet holds V0-VF, I, PC, SP, DT and ST, in that order.
+/// I and PC take 2 bytes, little endian, and the rest 1 byte each.
+/// gdb learns that layout from the target.xml description served
+/// through qXfer. Memory packets address the whole 64 KiB of RAM.
+///
+/// Supported: ?, g, G, p, P, m, M, c, s, software and hardware
+/// breakpoints (Z0/Z1), watchpoints (Z2-Z4), qXfer:features:read,
+/// Ctrl-C, D and k.
+pub struct GdbServer {
+    debugger: Debugger,
+    last_stop: String, // Reply to the ? packet
+}
+
+impl GdbServer {
+
+    pub fn new(debugger: Debugger) -> Self {
+        Self {
+            debugger,
+            last_stop: "S05".to_string(),
+        }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn into_inner(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Serves a single gdb session, until gdb detaches, kills the
+    /// program, or disconnects. Input is read from a separate thread,
+    /// so a running program can be interrupted.
+    pub fn serve<R, W>(&mut self, input: R, output: W) -> io::Result<()>
+    where
+        R: Read + Send + 'static,
+        W: Write,
+    {
+        let mut conn = Connection::new(input, output);
+
+        while let Some(packet) = conn.read_packet()? {
+            let reply: String = match packet.chars().next() {
+                Some('k') => return Ok(()),
+                Some('D') => return conn.send("OK"),
+                Some('c') => match self.resume(&packet[1..], &mut conn) {
+                    Some(reply) => reply,
+                    None => return Ok(()),
+                },
+                _ => self.handle(&packet),
+            };
+            conn.send(&reply)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replies to the packets that don't run the program for long
+    fn handle(&mut self, packet: &str) -> String {
+        if packet.is_empty() {
+            return String::new();
+        }
+        let (command, args) = packet.split_at(1);
+        let emu: &mut Emulator = self.debugger.emulator_mut();
+
+        let reply: Option<String> = match command {
+            "?" => Some(self.last_stop.clone()),
+            "g" => Some((0..NUM_REGS).map(|reg| encode_register(reg, read_register(emu, reg))).collect()),
+            "G" => write_registers(emu, args),
+            "p" => parse_hex(args).filter(|&reg| reg < NUM_REGS).map(|reg| encode_register(reg, read_register(emu, reg))),
+            "P" => write_register_packet(emu, args),
+            "m" => read_memory(emu, args),
+            "M" => write_memory(emu, args),
+            "s" => {
+                if let Some(pc) = parse_hex(args) {
+                    emu.set_pc(pc as u16);
+                }
+                let reason = self.debugger.step();
+                Some(self.stopped(reason))
+            }
+            "Z" | "z" => self.set_breakpoint(command == "Z", args),
+            "H" => Some("OK".to_string()),
+            "q" if args.starts_with("Supported") => Some(format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)),
+            "q" if args.starts_with("Xfer:features:read:") => read_features(&args["Xfer:features:read:".len()..]),
+            "q" if args == "Attached" => Some("1".to_string()),
+            _ => Some(String::new()),
+        };
+
+        reply.unwrap_or_else(|| ERROR_REPLY.to_string())
+    }
+
+    /// Runs until something stops the program, or gdb sends a Ctrl-C.
+    /// Returns None if gdb disconnected meanwhile.
+    fn resume<W: Write>(&mut self, args: &str, conn: &mut Connection<W>) -> Option<String> {
+        if let Some(pc) = parse_hex(args) {
+            self.debugger.emulator_mut().set_pc(pc as u16);
+        }
+
+        let mut reason: StopReason = self.debugger.run(RUN_CHUNK);
+        while reason == StopReason::TickLimit {
+            if conn.interrupted()? {
+                self.last_stop = "S02".to_string();
+                return Some(self.last_stop.clone());
+            }
+            // run() doesn't stop on the first instruction, so check it here
+            reason = match self.debugger.breakpoint_hit() {
+                Some(addr) => StopReason::Breakpoint { addr },
+                None => self.debugger.run(RUN_CHUNK),
+            };
+        }
+
+        Some(self.stopped(reason))
+    }
+
+    /// The stop reply for reason, remembered for the ? packet
+    fn stopped(&mut self, reason: StopReason) -> String {
+        self.last_stop = match reason {
+            StopReason::Watchpoint { addr, access: Access::Read } => format!("T05rwatch:{:x};", addr),
+            StopReason::Watchpoint { addr, access: Access::Write } => format!("T05watch:{:x};", addr),
+            StopReason::Error(EmulatorError::UnknownOpcode { .. }) => "S04".to_string(),
+            StopReason::Error(_) => "S0b".to_string(),
+            StopReason::Halted => "W00".to_string(),
+            _ => "S05".to_string(),
+        };
+        self.last_stop.clone()
+    }
+
+    /// Z/z packets: type,addr,kind. For watchpoints, kind is the length.
+    fn set_breakpoint(&mut self, insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(';').next()?.split(',');
+        let kind: &str = fields.next()?;
+        let addr: usize = parse_hex(fields.next()?)?;
+        let len: usize = parse_hex(fields.next()?)?;
+        let end: usize = addr.checked_add(len)?;
+
+        let (read, write): (bool, bool) = match kind {
+            "0" | "1" => {
+                let addr: u16 = u16::try_from(addr).ok()?;
+                if insert {
+                    self.debugger.add_breakpoint(addr);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => (false, true),
+            "3" => (true, false),
+            "4" => (true, true),
+            _ => return Some(String::new()),
+        };
+
+        if insert {
+            self.debugger.add_watchpoint(addr..end, read, write);
+        } else {
+            self.debugger.remove_watchpoint(addr..end);
+        }
+        Some("OK".to_string())
+    }
+}
+
+fn register_name(reg: usize) -> String {
+    match reg {
+        REG_I => "i".to_string(),
+        REG_PC => "pc".to_string(),
+        REG_SP => "sp".to_string(),
+        REG_DT => "dt".to_string(),
+        REG_ST => "st".to_string(),
+        _ => format!("v{:x}", reg),
+    }
+}
+
+/// The register layout of the g packet, for gdb
+fn target_xml() -> String {
+    let mut xml: String = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.rusty-chip8.core\">\n",
+    );
+    for reg in 0..NUM_REGS {
+        let kind: &str = match reg {
+            REG_PC => "code_ptr",
+            REG_I => "data_ptr",
+            _ if register_size(reg) == 2 => "uint16",
+            _ => "uint8",
+        };
+        xml.push_str(&format!(
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>\n",
+            register_name(reg),
+            8 * register_size(reg),
+            kind,
+            reg
+        ));
+    }
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+/// qXfer:features:read:annex:offset,length. Only target.xml is served,
+/// in chunks starting with m when there's more to read, l for the last one.
+fn read_features(args: &str) -> Option<String> {
+    let (annex, range) = args.split_once(':')?;
+    let (offset, len) = range.split_once(',')?;
+    if annex != "target.xml" {
+        return None;
+    }
+
+    let xml: String = target_xml();
+    let start: usize = parse_hex(offset)?.min(xml.len());
+    // The m or l marker takes a byte of the packet
+    let len: usize = parse_hex(len)?.min(PACKET_SIZE - 1);
+    let end: usize = start.saturating_add(len).min(xml.len());
+    let marker: char = if end < xml.len() { 'm' } else { 'l' };
+    Some(format!("{}{}", marker, &xml[start..end]))
+}
+
+fn register_size(reg: usize) -> usize {
+    match reg {
+        REG_I | REG_PC => 2,
+        _ => 1,
+    }
+}
+
+fn read_register(emu: &Emulator, reg: usize) -> u16 {
+    match reg {
+        REG_I => emu.i_reg(),
+        REG_PC => emu.pc(),
+        REG_SP => emu.sp(),
+        REG_DT => emu.dt() as u16,
+        REG_ST => emu.st() as u16,
+        _ => emu.v_reg()[reg] as u16,
+    }
+}
+
+fn write_register(emu: &mut Emulator, reg: usize, value: u16) {
+    match reg {
+        REG_I => emu.set_i_reg(value),
+        REG_PC => emu.set_pc(value),
+        REG_SP => emu.set_sp(value),
+        REG_DT => emu.set_dt(value as u8),
+        REG_ST => emu.set_st(value as u8),
+        _ => emu.set_v_reg(reg, value as u8),
+    }
+}
+
+/// Little endian, as many bytes as the register holds
+fn encode_register(reg: usize, value: u16) -> String {
+    (0..register_size(reg)).map(|idx| format!("{:02x}", (value >> (8 * idx)) as u8)).collect()
+}
+
+fn decode_register(bytes: &[u8]) -> u16 {
+    bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u16)
+}
+
+fn write_registers(emu: &mut Emulator, args: &str) -> Option<String> {
+    let bytes: Vec<u8> = decode_hex(args)?;
+    if bytes.len() != (0..NUM_REGS).map(register_size).sum::<usize>() {
+        return None;
+    }
+
+    let mut offset: usize = 0;
+    for reg in 0..NUM_REGS {
+        let size: usize = register_size(reg);
+        write_register(emu, reg, decode_register(&bytes[offset..offset + size]));
+        offset += size;
+    }
+    Some("OK".to_string())
+}
+
+/// P packet: reg=value
+fn write_register_packet(emu: &mut Emulator, args: &str) -> Option<String> {
+    let (reg, value) = args.split_once('=')?;
+    let reg: usize = parse_hex(reg).filter(|&reg| reg < NUM_REGS)?;
+    let bytes: Vec<u8> = decode_hex(value).filter(|bytes| bytes.len() == register_size(reg))?;
+
+    write_register(emu, reg, decode_register(&bytes));
+    Some("OK".to_string())
+}
+
+/// m packet: addr,len. The hex reply has to fit in a packet.
+fn read_memory(emu: &Emulator, args: &str) -> Option<String> {
+    let (addr, len) = args.split_once(',')?;
+    let addr: usize = parse_hex(addr)?;
+    let len: usize = parse_hex(len).filter(|&len| len <= PACKET_SIZE / 2)?;
+    let bytes: &[u8] = emu.ram().get(addr..addr.checked_add(len)?)?;
+    Some(encode_hex(bytes))
+}
+
+/// M packet: addr,len:bytes
+fn write_memory(emu: &mut Emulator, args: &str) -> Option<String> {
+    let (range, data) = args.split_once(':')?;
+    let (addr, len) = range.split_once(',')?;
+    let addr: usize = parse_hex(addr)?;
+    let bytes: Vec<u8> = decode_hex(data).filter(|bytes| bytes.len() == parse_hex(len).unwrap_or(0))?;
+
+    if addr.checked_add(bytes.len())? > RAM_SIZE {
+        return None;
+    }
+    for (offset, &byte) in bytes.iter().enumerate() {
+        emu.poke(addr + offset, byte).ok()?;
+    }
+    Some("OK".to_string())
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(text.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, &byte| sum.wrapping_add(byte))
+}
+
+/// Packet framing over a byte stream
+struct Connection<W: Write> {
+    bytes: Receiver<u8>,
+    pending: VecDeque<u8>, // Read while checking for Ctrl-C
+    output: W,
+}
+
+impl<W: Write> Connection<W> {
+
+    fn new<R: Read + Send + 'static>(input: R, output: W) -> Self {
+        let (sender, bytes) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(input).bytes() {
+                match byte {
+                    Ok(byte) if sender.send(byte).is_ok() => {}
+                    _ => break,
+                }
+            }
+        });
+
+        Self {
+            bytes,
+            pending: VecDeque::new(),
+            output,
+        }
+    }
+
+    /// None once gdb disconnected
+    fn next_byte(&mut self) -> Option<u8> {
+        self.pending.pop_front().or_else(|| self.bytes.recv().ok())
+    }
+
+    /// Checks for a Ctrl-C without blocking, keeping anything else
+    /// for later. None once gdb disconnected.
+    fn interrupted(&mut self) -> Option<bool> {
+        loop {
+            match self.bytes.try_recv() {
+                Ok(0x03) => return Some(true),
+                Ok(byte) => self.pending.push_back(byte),
+                Err(TryRecvError::Empty) => return Some(false),
+                Err(TryRecvError::Disconnected) => return None,
+            }
+        }
+    }
+
+    /// Waits for the next well formed packet and acks it.
+    /// None once gdb disconnected.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Acks and stray Ctrl-Cs come in between packets
+            loop {
+                match self.next_byte() {
+                    Some(b'$') => break,
+                    Some(_) => {}
+                    None => return Ok(None),
+                }
+            }
+
+            let mut data: Vec<u8> = Vec::new();
+            loop {
+                match self.next_byte() {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+
+            let (Some(high), Some(low)) = (self.next_byte(), self.next_byte()) else {
+                return Ok(None);
+            };
+            let expected: Option<u8> = decode_hex(&String::from_utf8_lossy(&[high, low])).map(|sum| sum[0]);
+
+            if expected == Some(checksum(&data)) {
+                self.output.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.output.write_all(b"-")?;
+            self.output.flush()?;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        write!(self.output, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::JoinHandle;
+
+    /// 0x200: V0 = 5
+    /// 0x202: I = 0x300
+    /// 0x204: STORE V0..V0
+    /// 0x206: JMP 0x206
+    const ROM: [u8; 8] = [0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06];
+
+    /// Stands in for gdb, over a local socket
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+
+        fn request(&mut self, packet: &str) -> String {
+            write!(self.stream, "${}#{:02x}", packet, checksum(packet.as_bytes())).unwrap();
+            self.reply()
+        }
+
+        fn reply(&mut self) -> String {
+            let mut byte: [u8; 1] = [0];
+            while byte[0] != b'$' {
+                self.stream.read_exact(&mut byte).unwrap();
+            }
+
+            let mut data: Vec<u8> = Vec::new();
+            loop {
+                self.stream.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' {
+                    break;
+                }
+                data.push(byte[0]);
+            }
+            let mut sum: [u8; 2] = [0; 2];
+            self.stream.read_exact(&mut sum).unwrap();
+            assert_eq!(decode_hex(std::str::from_utf8(&sum).unwrap()), Some(vec![checksum(&data)]));
+
+            String::from_utf8(data).unwrap()
+        }
+    }
+
+    fn connect() -> (Client, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let mut emu = Emulator::new(Quirks::super_chip());
+            emu.load(&ROM).unwrap();
+            let (stream, _) = listener.accept().unwrap();
+            GdbServer::new(Debugger::new(emu)).serve(stream.try_clone().unwrap(), stream).unwrap();
+        });
+
+        (Client { stream: TcpStream::connect(addr).unwrap() }, server)
+    }
+
+    #[test]
+    fn reads_and_writes_registers_and_memory() {
+        let (mut client, server) = connect();
+
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p0"), "05");
+        assert_eq!(client.request("p10"), "0003");
+        assert_eq!(client.request("p11"), "0402");
+
+        assert_eq!(client.request("P0=07"), "OK");
+        assert!(client.request("g").starts_with("07"));
+        assert_eq!(client.request("M300,2:abcd"), "OK");
+        assert_eq!(client.request("m300,2"), "abcd");
+        assert_eq!(client.request("mffff,2"), "E01");
+        assert_eq!(client.request("m0,800").len(), PACKET_SIZE);
+        assert_eq!(client.request("m0,801"), "E01");
+        // Addresses that overflow are errors, not panics
+        assert_eq!(client.request("Mffffffffffffffff,1:00"), "E01");
+        assert_eq!(client.request("mffffffffffffffff,2"), "E01");
+        assert_eq!(client.request("Z2,ffffffffffffffff,2"), "E01");
+        assert_eq!(client.request("z4,ffffffffffffffff,2"), "E01");
+
+        assert_eq!(client.request("D"), "OK");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn serves_the_register_layout_in_chunks() {
+        let (mut client, server) = connect();
+
+        assert!(client.request("qSupported:multiprocess+").contains("qXfer:features:read+"));
+        let mut xml: String = String::new();
+        loop {
+            let chunk: String = client.request(&format!("qXfer:features:read:target.xml:{:x},40", xml.len()));
+            xml.push_str(&chunk[1..]);
+            if chunk.starts_with('l') {
+                break;
+            }
+            assert!(chunk.starts_with('m'));
+        }
+        assert_eq!(xml, target_xml());
+        assert!(xml.contains("<reg name=\"vf\" bitsize=\"8\" type=\"uint8\" regnum=\"15\"/>"));
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\" regnum=\"17\"/>"));
+        assert_eq!(client.request("qXfer:features:read:other.xml:0,40"), "E01");
+
+        assert_eq!(client.request("D"), "OK");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn continues_until_breakpoints_watchpoints_or_ctrl_c() {
+        let (mut client, server) = connect();
+
+        assert_eq!(client.request("Z0,204,2"), "OK");
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("p11"), "0402");
+        assert_eq!(client.request("z0,204,2"), "OK");
+
+        assert_eq!(client.request("Z2,300,1"), "OK");
+        assert_eq!(client.request("c"), "T05watch:300;");
+
+        // Now stuck in the jump loop
+        write!(client.stream, "$c#63").unwrap();
+        client.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(client.reply(), "S02");
+        assert_eq!(client.request("?"), "S02");
+
+        client.stream.write_all(b"$k#6b").unwrap();
+        server.join().unwrap();
+    }
+}
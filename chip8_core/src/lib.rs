@@ -1,5 +1,22 @@
+pub mod asm;
+pub mod audio;
+pub mod cache;
+pub mod debugger;
+pub mod disasm;
+pub mod dump;
 pub mod emulator;
+pub mod error;
 pub mod fontset;
+pub mod gdb;
+pub mod octo;
+pub mod operations;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod screen;
+pub mod state;
+pub mod timing;
+pub mod trace;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,830 @@
+use crate::emulator::{RAM_SIZE, START_ADDR};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// Most macro invocations in a program, so recursive macros can't hang the compiler
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// What went wrong, and on which line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OctoError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for OctoError {}
+
+/// Compiles Octo source into a ROM, loaded at START_ADDR.
+///
+/// The ROM starts with a jump to the `main` label. Supported:
+/// - `: label`, `:next label`, `:org`, `:byte`, `:unpack`, and bare numbers as data
+/// - `:const`, `:alias`, `:calc name { expr }` and `:macro name args { body }`
+/// - every Octo instruction, including the SUPER-CHIP and XO-CHIP ones
+/// - `if ... then`, `if ... begin ... else ... end`, and `loop ... while ... again`,
+///   with the `==`, `!=`, `<`, `>`, `<=`, `>=`, `key` and `-key` conditions
+///
+/// `:calc` expressions are evaluated right to left, with no precedence,
+/// as in Octo. `:breakpoint` and `:monitor` are accepted and ignored.
+pub fn compile(source: &str) -> Result<Vec<u8>, OctoError> {
+    let mut compiler = Compiler::new(source);
+    compiler.compile()?;
+    Ok(compiler.rom)
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// An address to fill in once its label is defined
+struct Fixup {
+    offset: usize, // Into the ROM
+    label: String,
+    line: usize,
+    long: bool, // 16 bits address (i := long), instead of 12
+}
+
+struct Loop {
+    start: u16,
+    breaks: Vec<usize>, // Offsets of the jumps out of it
+}
+
+/// The instructions checking a condition
+struct Condition {
+    prelude: Vec<u16>,
+    skip_if_true: u16,
+    skip_if_false: u16,
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    line: usize, // Of the last token read
+    rom: Vec<u8>,
+    pc: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    loops: Vec<Loop>,
+    blocks: Vec<usize>, // Offsets of the jumps to the else or end of each if
+    expansions: usize,
+}
+
+impl Compiler {
+
+    fn new(source: &str) -> Self {
+        let tokens: VecDeque<Token> = source
+            .lines()
+            .enumerate()
+            .flat_map(|(idx, line)| {
+                let code: &str = line.split('#').next().unwrap_or("");
+                code.split_whitespace().map(move |text| Token { text: text.to_string(), line: idx + 1 })
+            })
+            .collect();
+
+        Self {
+            tokens,
+            line: 1,
+            rom: Vec::new(),
+            pc: START_ADDR as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+        }
+    }
+
+    fn compile(&mut self) -> Result<(), OctoError> {
+        self.jump_to(0x1000, "main".to_string())?;
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if !self.loops.is_empty() {
+            return Err(self.error("a loop is missing its again".to_string()));
+        }
+        if !self.blocks.is_empty() {
+            return Err(self.error("an if block is missing its end".to_string()));
+        }
+
+        for fixup in &self.fixups {
+            let Some(&addr) = self.labels.get(&fixup.label) else {
+                return Err(OctoError { line: fixup.line, message: format!("undefined name '{}'", fixup.label) });
+            };
+            if fixup.long {
+                self.rom[fixup.offset..fixup.offset + 2].copy_from_slice(&addr.to_be_bytes());
+            } else if addr > 0xFFF {
+                let message: String = format!("'{}' is past 0xFFF, out of reach without i := long", fixup.label);
+                return Err(OctoError { line: fixup.line, message });
+            } else {
+                self.rom[fixup.offset] |= (addr >> 8) as u8;
+                self.rom[fixup.offset + 1] = addr as u8;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn error(&self, message: String) -> OctoError {
+        OctoError { line: self.line, message }
+    }
+
+    fn next(&mut self) -> Result<Token, OctoError> {
+        let token: Token = self.tokens.pop_front().ok_or_else(|| self.error("unexpected end of file".to_string()))?;
+        self.line = token.line;
+        Ok(token)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), OctoError> {
+        let token: Token = self.next()?;
+        if token.text != text {
+            return Err(self.error(format!("expected '{}', got '{}'", text, token.text)));
+        }
+        Ok(())
+    }
+
+    // Output
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), OctoError> {
+        if self.pc >= RAM_SIZE {
+            return Err(self.error("the program doesn't fit in RAM".to_string()));
+        }
+        let offset: usize = self.pc - START_ADDR as usize;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.pc += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, op: u16) -> Result<(), OctoError> {
+        let [high, low] = op.to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    /// Emits opcode | NNN, the address being a label, constant or number
+    fn jump_to(&mut self, opcode: u16, target: String) -> Result<(), OctoError> {
+        let addr: u16 = match self.known_value(&target) {
+            Some(addr) if (0..=0xFFF).contains(&addr) => addr as u16,
+            Some(_) => return Err(self.error(format!("{} is past 0xFFF", target))),
+            None => {
+                self.fixup(target, false)?;
+                0
+            }
+        };
+        self.emit(opcode | addr)
+    }
+
+    /// Waits for the label to be defined to fill in the instruction at pc
+    fn fixup(&mut self, label: String, long: bool) -> Result<(), OctoError> {
+        if !is_name(&label) {
+            return Err(self.error(format!("'{}' isn't a valid name", label)));
+        }
+        let offset: usize = self.pc - START_ADDR as usize + if long { 2 } else { 0 };
+        self.fixups.push(Fixup { offset, label, line: self.line, long });
+        Ok(())
+    }
+
+    /// Points the jump at offset to the current pc
+    fn land_jump(&mut self, offset: usize) {
+        self.rom[offset] |= (self.pc >> 8) as u8 & 0xF;
+        self.rom[offset + 1] = self.pc as u8;
+    }
+
+    // Operands
+
+    fn name(&mut self) -> Result<String, OctoError> {
+        let token: Token = self.next()?;
+        if !is_name(&token.text) || self.register_of(&token.text).is_some() {
+            return Err(self.error(format!("'{}' isn't a valid name", token.text)));
+        }
+        Ok(token.text)
+    }
+
+    fn define_label(&mut self, name: String, addr: usize) -> Result<(), OctoError> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return Err(self.error(format!("'{}' is already defined", name)));
+        }
+        self.labels.insert(name, addr as u16);
+        Ok(())
+    }
+
+    fn register_of(&self, text: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(text) {
+            return Some(reg);
+        }
+        let digit: &str = text.strip_prefix(['v', 'V']).filter(|digit| digit.len() == 1)?;
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn register(&mut self) -> Result<u16, OctoError> {
+        let token: Token = self.next()?;
+        match self.register_of(&token.text) {
+            Some(reg) => Ok(reg as u16),
+            None => Err(self.error(format!("expected a register, got '{}'", token.text))),
+        }
+    }
+
+    /// A number, constant or defined label
+    fn known_value(&self, text: &str) -> Option<i64> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).map(|&value| value.floor() as i64))
+            .or_else(|| self.labels.get(text).map(|&addr| addr as i64))
+    }
+
+    /// The value of the token, or of the { expression } it opens
+    fn value_of(&mut self, token: Token) -> Result<i64, OctoError> {
+        if token.text == "{" {
+            return Ok(self.calc()?.floor() as i64);
+        }
+        self.known_value(&token.text).ok_or_else(|| self.error(format!("unknown name '{}'", token.text)))
+    }
+
+    fn ranged(&mut self, token: Token, min: i64, max: i64) -> Result<u16, OctoError> {
+        let value: i64 = self.value_of(token)?;
+        if value < min || value > max {
+            return Err(self.error(format!("{} doesn't fit between {} and {}", value, min, max)));
+        }
+        Ok(value as u16)
+    }
+
+    /// Negative bytes are stored as two's complement
+    fn byte(&mut self) -> Result<u16, OctoError> {
+        let token: Token = self.next()?;
+        Ok(self.ranged(token, -128, 255)? & 0xFF)
+    }
+
+    fn nibble(&mut self) -> Result<u16, OctoError> {
+        let token: Token = self.next()?;
+        self.ranged(token, 0, 15)
+    }
+
+    // :calc expressions
+
+    /// Evaluates the expression up to the closing brace
+    fn calc(&mut self) -> Result<f64, OctoError> {
+        let mut tokens: Vec<Token> = Vec::new();
+        loop {
+            let token: Token = self.next()?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+
+        let mut pos: usize = 0;
+        let value: f64 = self.calc_expr(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(self.error(format!("unexpected '{}' in expression", tokens[pos].text)));
+        }
+        Ok(value)
+    }
+
+    fn calc_expr(&self, tokens: &[Token], pos: &mut usize) -> Result<f64, OctoError> {
+        let left: f64 = self.calc_term(tokens, pos)?;
+        let Some(op) = tokens.get(*pos).filter(|token| token.text != ")") else {
+            return Ok(left);
+        };
+        *pos += 1;
+        let right: f64 = self.calc_expr(tokens, pos)?;
+
+        let (a, b): (i64, i64) = (left as i64, right as i64);
+        let value: f64 = match op.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" | ">>" => {
+                let shifted: Option<i64> = u32::try_from(b)
+                    .ok()
+                    .and_then(|b| if op.text == "<<" { a.checked_shl(b) } else { a.checked_shr(b) });
+                shifted.ok_or_else(|| self.error("shift amount out of range".to_string()))? as f64
+            }
+            "<" => (left < right) as i64 as f64,
+            ">" => (left > right) as i64 as f64,
+            "<=" => (left <= right) as i64 as f64,
+            ">=" => (left >= right) as i64 as f64,
+            "==" => (left == right) as i64 as f64,
+            "!=" => (left != right) as i64 as f64,
+            other => return Err(self.error(format!("unknown operator '{}'", other))),
+        };
+        Ok(value)
+    }
+
+    fn calc_term(&self, tokens: &[Token], pos: &mut usize) -> Result<f64, OctoError> {
+        let token: &Token = tokens.get(*pos).ok_or_else(|| self.error("incomplete expression".to_string()))?;
+        *pos += 1;
+
+        let unary = |f: fn(f64) -> f64, pos: &mut usize| self.calc_term(tokens, pos).map(f);
+        match token.text.as_str() {
+            "(" => {
+                let value: f64 = self.calc_expr(tokens, pos)?;
+                if tokens.get(*pos).map(|token| token.text.as_str()) != Some(")") {
+                    return Err(self.error("missing ')' in expression".to_string()));
+                }
+                *pos += 1;
+                Ok(value)
+            }
+            "-" => unary(|value| -value, pos),
+            "~" => unary(|value| !(value as i64) as f64, pos),
+            "!" => unary(|value| (value == 0.0) as i64 as f64, pos),
+            "floor" => unary(f64::floor, pos),
+            "ceil" => unary(f64::ceil, pos),
+            "abs" => unary(f64::abs, pos),
+            "sqrt" => unary(f64::sqrt, pos),
+            "sin" => unary(f64::sin, pos),
+            "cos" => unary(f64::cos, pos),
+            text => parse_number(text)
+                .map(|value| value as f64)
+                .or_else(|| self.constants.get(text).copied())
+                .or_else(|| self.labels.get(text).map(|&addr| addr as f64))
+                .ok_or_else(|| self.error(format!("unknown name '{}'", text))),
+        }
+    }
+
+    // Statements
+
+    fn statement(&mut self) -> Result<(), OctoError> {
+        let token: Token = self.next()?;
+
+        match token.text.as_str() {
+            ":" => {
+                let name: String = self.name()?;
+                self.define_label(name, self.pc)?;
+            }
+            ":next" => {
+                let name: String = self.name()?;
+                self.define_label(name, self.pc + 1)?;
+            }
+            ":const" => {
+                let name: String = self.name()?;
+                let token: Token = self.next()?;
+                let value: i64 = self.value_of(token)?;
+                self.constants.insert(name, value as f64);
+            }
+            ":calc" => {
+                let name: String = self.name()?;
+                self.expect("{")?;
+                let value: f64 = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name: String = self.name()?;
+                let reg: u16 = self.register()?;
+                self.aliases.insert(name, reg as u8);
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let byte: u16 = self.byte()?;
+                self.emit_byte(byte as u8)?;
+            }
+            ":org" => {
+                let token: Token = self.next()?;
+                self.pc = self.ranged(token, START_ADDR as i64, RAM_SIZE as i64 - 1)? as usize;
+            }
+            ":unpack" => {
+                let nibble: u16 = self.nibble()?;
+                let token: Token = self.next()?;
+                let addr: u16 = self.ranged(token, 0, 0xFFF)?;
+                self.emit(0x6000 | (nibble << 4) | (addr >> 8))?;
+                self.emit(0x6100 | (addr & 0xFF))?;
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            ";" | "return" => self.emit(0x00EE)?,
+            "clear" => self.emit(0x00E0)?,
+            "scroll-right" => self.emit(0x00FB)?,
+            "scroll-left" => self.emit(0x00FC)?,
+            "exit" => self.emit(0x00FD)?,
+            "lores" => self.emit(0x00FE)?,
+            "hires" => self.emit(0x00FF)?,
+            "audio" => self.emit(0xF002)?,
+            "scroll-down" => {
+                let n: u16 = self.nibble()?;
+                self.emit(0x00C0 | n)?;
+            }
+            "scroll-up" => {
+                let n: u16 = self.nibble()?;
+                self.emit(0x00D0 | n)?;
+            }
+            "plane" => {
+                let n: u16 = self.nibble()?;
+                self.emit(0xF001 | (n << 8))?;
+            }
+            "bcd" => self.reg_op(0xF033)?,
+            "saveflags" => self.reg_op(0xF075)?,
+            "loadflags" => self.reg_op(0xF085)?,
+            "save" | "load" => {
+                let x: u16 = self.register()?;
+                let store: bool = token.text == "save";
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y: u16 = self.register()?;
+                    self.emit(if store { 0x5002 } else { 0x5003 } | (x << 8) | (y << 4))?;
+                } else {
+                    self.emit(if store { 0xF055 } else { 0xF065 } | (x << 8))?;
+                }
+            }
+            "sprite" => {
+                let x: u16 = self.register()?;
+                let y: u16 = self.register()?;
+                let n: u16 = self.nibble()?;
+                self.emit(0xD000 | (x << 8) | (y << 4) | n)?;
+            }
+            "jump" | "jump0" | "native" => {
+                let opcode: u16 = match token.text.as_str() {
+                    "jump" => 0x1000,
+                    "jump0" => 0xB000,
+                    _ => 0x0000,
+                };
+                let target: Token = self.next()?;
+                self.jump_to(opcode, target.text)?;
+            }
+            "loop" => self.loops.push(Loop { start: self.pc as u16, breaks: Vec::new() }),
+            "again" => {
+                let Some(done) = self.loops.pop() else {
+                    return Err(self.error("again without a loop".to_string()));
+                };
+                self.emit(0x1000 | done.start)?;
+                for offset in done.breaks {
+                    self.land_jump(offset);
+                }
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error("while outside of a loop".to_string()));
+                }
+                let condition: Condition = self.condition()?;
+                self.emit_condition(&condition, true)?;
+                let offset: usize = self.pc - START_ADDR as usize;
+                self.emit(0x1000)?;
+                self.loops.last_mut().unwrap().breaks.push(offset);
+            }
+            "if" => {
+                let condition: Condition = self.condition()?;
+                match self.next()?.text.as_str() {
+                    "then" => self.emit_condition(&condition, false)?,
+                    "begin" => {
+                        self.emit_condition(&condition, true)?;
+                        self.blocks.push(self.pc - START_ADDR as usize);
+                        self.emit(0x1000)?;
+                    }
+                    other => return Err(self.error(format!("expected then or begin, got '{}'", other))),
+                }
+            }
+            "else" => {
+                let Some(offset) = self.blocks.pop() else {
+                    return Err(self.error("else without an if".to_string()));
+                };
+                let else_jump: usize = self.pc - START_ADDR as usize;
+                self.emit(0x1000)?;
+                self.land_jump(offset);
+                self.blocks.push(else_jump);
+            }
+            "end" => {
+                let Some(offset) = self.blocks.pop() else {
+                    return Err(self.error("end without an if".to_string()));
+                };
+                self.land_jump(offset);
+            }
+            "i" => self.index_op()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                self.reg_op(match token.text.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                })?;
+            }
+            text if self.register_of(text).is_some() => {
+                let x: u16 = self.register_of(text).unwrap() as u16;
+                self.register_op(x)?;
+            }
+            text if self.macros.contains_key(text) => self.expand(&token)?,
+            text if parse_number(text).is_some() || self.constants.contains_key(text) => {
+                let byte: u16 = self.ranged(token, -128, 255)?;
+                self.emit_byte(byte as u8)?;
+            }
+            // Anything else calls a subroutine
+            _ => self.jump_to(0x2000, token.text)?,
+        }
+
+        Ok(())
+    }
+
+    /// Emits opcode | X << 8, reading X
+    fn reg_op(&mut self, opcode: u16) -> Result<(), OctoError> {
+        let x: u16 = self.register()?;
+        self.emit(opcode | (x << 8))
+    }
+
+    /// vx := ..., vx += ... and the other register operators
+    fn register_op(&mut self, x: u16) -> Result<(), OctoError> {
+        let op: Token = self.next()?;
+        let rhs: Token = self.next()?;
+
+        if let Some(y) = self.register_of(&rhs.text) {
+            let opcode: u16 = match op.text.as_str() {
+                ":=" => 0x8000,
+                "|=" => 0x8001,
+                "&=" => 0x8002,
+                "^=" => 0x8003,
+                "+=" => 0x8004,
+                "-=" => 0x8005,
+                ">>=" => 0x8006,
+                "=-" => 0x8007,
+                "<<=" => 0x800E,
+                other => return Err(self.error(format!("can't use '{}' between registers", other))),
+            };
+            return self.emit(opcode | (x << 8) | ((y as u16) << 4));
+        }
+
+        let op: u16 = match (op.text.as_str(), rhs.text.as_str()) {
+            (":=", "key") => 0xF00A | (x << 8),
+            (":=", "delay") => 0xF007 | (x << 8),
+            (":=", "random") => 0xC000 | (x << 8) | self.byte()?,
+            (":=", _) => 0x6000 | (x << 8) | (self.ranged(rhs, -128, 255)? & 0xFF),
+            ("+=", _) => 0x7000 | (x << 8) | (self.ranged(rhs, -128, 255)? & 0xFF),
+            ("-=", _) => 0x7000 | (x << 8) | (self.ranged(rhs, -255, 128)?.wrapping_neg() & 0xFF),
+            (other, _) => return Err(self.error(format!("can't use '{}' with a number", other))),
+        };
+        self.emit(op)
+    }
+
+    /// i := addr, i := long addr, i := hex vx, i := bighex vx, i += vx
+    fn index_op(&mut self) -> Result<(), OctoError> {
+        let op: Token = self.next()?;
+        if op.text == "+=" {
+            return self.reg_op(0xF01E);
+        }
+        if op.text != ":=" {
+            return Err(self.error(format!("can't use '{}' with i", op.text)));
+        }
+
+        let rhs: Token = self.next()?;
+        match rhs.text.as_str() {
+            "hex" => self.reg_op(0xF029),
+            "bighex" => self.reg_op(0xF030),
+            "long" => {
+                let target: Token = self.next()?;
+                let addr: u16 = match self.known_value(&target.text) {
+                    Some(addr) if (0..=0xFFFF).contains(&addr) => addr as u16,
+                    Some(_) => return Err(self.error(format!("{} is past 0xFFFF", target.text))),
+                    None => {
+                        self.fixup(target.text, true)?;
+                        0
+                    }
+                };
+                self.emit(0xF000)?;
+                self.emit(addr)
+            }
+            "{" => {
+                let addr: u16 = self.ranged(rhs, 0, 0xFFF)?;
+                self.emit(0xA000 | addr)
+            }
+            _ => self.jump_to(0xA000, rhs.text),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, OctoError> {
+        let x: u16 = self.register()?;
+        let op: Token = self.next()?;
+
+        let (skip_if_true, skip_if_false): (u16, u16) = match op.text.as_str() {
+            "key" => (0xE09E | (x << 8), 0xE0A1 | (x << 8)),
+            "-key" => (0xE0A1 | (x << 8), 0xE09E | (x << 8)),
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {
+                let rhs: Token = self.next()?;
+                let rhs_reg: Option<u16> = self.register_of(&rhs.text).map(|reg| reg as u16);
+                let rhs_byte: u16 = match rhs_reg {
+                    Some(_) => 0,
+                    None => self.ranged(rhs, -128, 255)? & 0xFF,
+                };
+
+                if op.text == "==" || op.text == "!=" {
+                    let (equal, not_equal): (u16, u16) = match rhs_reg {
+                        Some(y) => (0x5000 | (x << 8) | (y << 4), 0x9000 | (x << 8) | (y << 4)),
+                        None => (0x3000 | (x << 8) | rhs_byte, 0x4000 | (x << 8) | rhs_byte),
+                    };
+                    if op.text == "==" { (equal, not_equal) } else { (not_equal, equal) }
+                } else {
+                    // VF gets the no borrow flag of vx - rhs (for < and >=) or rhs - vx
+                    let load: u16 = match rhs_reg {
+                        Some(y) => 0x8F00 | (y << 4),
+                        None => 0x6F00 | rhs_byte,
+                    };
+                    let subtract: u16 = if op.text == "<" || op.text == ">=" { 0x8F07 } else { 0x8F05 };
+                    let (skip_if_flag, skip_if_no_flag): (u16, u16) = (0x4F00, 0x3F00);
+                    let condition = Condition {
+                        prelude: vec![load, subtract | (x << 4)],
+                        skip_if_true: if op.text.ends_with('=') { skip_if_flag } else { skip_if_no_flag },
+                        skip_if_false: if op.text.ends_with('=') { skip_if_no_flag } else { skip_if_flag },
+                    };
+                    return Ok(condition);
+                }
+            }
+            other => return Err(self.error(format!("unknown condition '{}'", other))),
+        };
+
+        Ok(Condition { prelude: Vec::new(), skip_if_true, skip_if_false })
+    }
+
+    /// Emits the check, skipping the next instruction if the condition is skip_if
+    fn emit_condition(&mut self, condition: &Condition, skip_if: bool) -> Result<(), OctoError> {
+        for &op in &condition.prelude {
+            self.emit(op)?;
+        }
+        self.emit(if skip_if { condition.skip_if_true } else { condition.skip_if_false })
+    }
+
+    /// :macro name params { body }
+    fn define_macro(&mut self) -> Result<(), OctoError> {
+        let name: String = self.name()?;
+        let mut params: Vec<String> = Vec::new();
+        loop {
+            let token: Token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+
+        let mut body: Vec<Token> = Vec::new();
+        let mut depth: usize = 1;
+        loop {
+            let token: Token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 1 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    /// Replaces the macro invocation with its body
+    fn expand(&mut self, invocation: &Token) -> Result<(), OctoError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("'{}' keeps expanding forever", invocation.text)));
+        }
+
+        let num_params: usize = self.macros[&invocation.text].params.len();
+        let mut args: HashMap<String, String> = HashMap::new();
+        for idx in 0..num_params {
+            let arg: Token = self.next()?;
+            args.insert(self.macros[&invocation.text].params[idx].clone(), arg.text);
+        }
+
+        let body = &self.macros[&invocation.text].body;
+        for token in body.iter().rev() {
+            let text: String = args.get(&token.text).cloned().unwrap_or_else(|| token.text.clone());
+            self.tokens.push_front(Token { text, line: token.line });
+        }
+        Ok(())
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    !text.is_empty() && !text.starts_with(|c: char| c.is_ascii_digit() || c == '-') && !text.contains(['{', '}'])
+}
+
+/// Decimal, 0x hex or 0b binary, maybe negative
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits): (bool, &str) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value: i64 = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    /// Runs the ROM until it gets stuck in a jump to itself
+    fn run(source: &str) -> Emulator {
+        let mut emulator = Emulator::default();
+        emulator.load(&compile(source).unwrap()).unwrap();
+        for _ in 0..10_000 {
+            let pc: u16 = emulator.pc();
+            let ram: &[u8] = emulator.ram();
+            if ram[pc as usize] == 0x10 | (pc >> 8) as u8 && ram[pc as usize + 1] == pc as u8 {
+                break;
+            }
+            emulator.tick().unwrap();
+        }
+        emulator
+    }
+
+    #[test]
+    fn compiles_main_and_loops() {
+        let rom = compile(": main v0 := 5 loop v0 += 1 again").unwrap();
+        assert_eq!(rom, [0x12, 0x02, 0x60, 0x05, 0x70, 0x01, 0x12, 0x04]);
+    }
+
+    #[test]
+    fn conditions_and_control_flow() {
+        let emulator = run("
+            : main
+                v0 := 0
+                v1 := 0
+                loop
+                    v0 += 1
+                    if v0 < 5 then v1 += 2
+                    while v0 != 10
+                again
+                if v1 == 8 begin v2 := 1 else v2 := 2 end
+                if v0 >= 10 then v3 := 1
+                if v0 > v1 begin v4 := 1 end
+            : done jump done
+        ");
+        assert_eq!(&emulator.v_reg()[..5], &[10, 8, 1, 1, 1]);
+    }
+
+    #[test]
+    fn macros_constants_and_data() {
+        let emulator = run("
+            :const SPEED 3
+            :alias counter v3
+            :calc DOUBLE { SPEED * 2 + 1 }
+            :macro twice reg { reg += DOUBLE reg += DOUBLE }
+            : sprite 0xF0 0x90 :byte { SPEED - 4 }
+            : main
+                counter := SPEED
+                twice counter
+                i := sprite
+                :unpack 0xA sprite
+                jump end
+            : end jump end
+        ");
+        // DOUBLE is SPEED * (2 + 1), with no precedence
+        assert_eq!(emulator.v_reg()[3], 3 + 9 + 9);
+        assert_eq!(emulator.i_reg(), 0x202);
+        assert_eq!(&emulator.v_reg()[..2], &[0xA2, 0x02]);
+        assert_eq!(&emulator.ram()[0x202..0x205], &[0xF0, 0x90, 0xFF]);
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let error = |source: &str| compile(source).unwrap_err().to_string();
+
+        assert_eq!(error(": main\n\njump nowhere"), "line 3: undefined name 'nowhere'");
+        assert_eq!(error(": main\nagain"), "line 2: again without a loop");
+        assert_eq!(error(": main\nv0 := 300"), "line 2: 300 doesn't fit between -128 and 255");
+        assert_eq!(error(": main\ni := long 0x10000"), "line 2: 0x10000 is past 0xFFFF");
+        assert_eq!(error(":calc x { 1 << 64 }"), "line 1: shift amount out of range");
+        assert_eq!(error(":calc x { 1 >> -1 }"), "line 1: shift amount out of range");
+        assert_eq!(error(":const far 70000\n: main\ni := long far"), "line 3: far is past 0xFFFF");
+        assert_eq!(error("v0 := 1"), "line 1: undefined name 'main'");
+    }
+}
@@ -0,0 +1,317 @@
+use std::fmt;
+
+/// A decoded CHIP-8 instruction.
+///
+/// Register operands (x, y) are indexes into the V registers,
+/// n/nn/nnn are the 4, 8 and 12 bit immediates embedded in the opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0000: do nothing
+    Nop,
+    /// 0NNN: call a machine code routine (ignored by modern interpreters)
+    Sys { nnn: u16 },
+    /// 00E0: clear screen
+    ClearScreen,
+    /// 00EE: return from subroutine
+    Return,
+    /// 00CN: scroll the screen N pixels down (SCHIP)
+    ScrollDown { n: u8 },
+    /// 00DN: scroll the screen N pixels up (XO-CHIP)
+    ScrollUp { n: u8 },
+    /// 00FB: scroll the screen 4 pixels right (SCHIP)
+    ScrollRight,
+    /// 00FC: scroll the screen 4 pixels left (SCHIP)
+    ScrollLeft,
+    /// 00FD: exit the interpreter (SCHIP)
+    Exit,
+    /// 00FE: switch to 64x32 low resolution (SCHIP)
+    LowRes,
+    /// 00FF: switch to 128x64 high resolution (SCHIP)
+    HighRes,
+    /// 1NNN: jump to NNN
+    Jump { nnn: u16 },
+    /// 2NNN: call subroutine at NNN
+    Call { nnn: u16 },
+    /// 3XNN: skip next if VX == NN
+    SkipIfRegEqualsVal { x: u8, nn: u8 },
+    /// 4XNN: skip next if VX != NN
+    SkipIfRegNotEqualsVal { x: u8, nn: u8 },
+    /// 5XY0: skip next if VX == VY
+    SkipIfRegEqualsReg { x: u8, y: u8 },
+    /// 5XY2: store VX..VY into RAM starting at I (XO-CHIP)
+    StoreRegRangeInRam { x: u8, y: u8 },
+    /// 5XY3: load VX..VY from RAM starting at I (XO-CHIP)
+    LoadRegRangeFromRam { x: u8, y: u8 },
+    /// 6XNN: VX = NN
+    AssignValToReg { x: u8, nn: u8 },
+    /// 7XNN: VX += NN
+    AddValToReg { x: u8, nn: u8 },
+    /// 8XY0: VX = VY
+    AssignRegToReg { x: u8, y: u8 },
+    /// 8XY1: VX |= VY
+    RegOrReg { x: u8, y: u8 },
+    /// 8XY2: VX &= VY
+    RegAndReg { x: u8, y: u8 },
+    /// 8XY3: VX ^= VY
+    RegXorReg { x: u8, y: u8 },
+    /// 8XY4: VX += VY
+    AddRegToReg { x: u8, y: u8 },
+    /// 8XY5: VX -= VY
+    SubRegToReg { x: u8, y: u8 },
+    /// 8XY6: VX >>= 1
+    RightShift { x: u8, y: u8 },
+    /// 8XY7: VX = VY - VX
+    OppositeSubRegToReg { x: u8, y: u8 },
+    /// 8XYE: VX <<= 1
+    LeftShift { x: u8, y: u8 },
+    /// 9XY0: skip next if VX != VY
+    SkipIfRegNotEqualsReg { x: u8, y: u8 },
+    /// ANNN: I = NNN
+    AssignAddrToRamPointer { nnn: u16 },
+    /// BNNN: jump to V0 + NNN
+    JumpWithOffset { nnn: u16 },
+    /// CXNN: VX = rand & NN
+    AssignRandomToReg { x: u8, nn: u8 },
+    /// DXYN: draw a N rows sprite at (VX, VY).
+    /// DXY0 draws a 16x16 sprite (SCHIP)
+    DrawSprite { x: u8, y: u8, n: u8 },
+    /// EX9E: skip next if key VX is pressed
+    SkipIfKeyPressed { x: u8 },
+    /// EXA1: skip next if key VX is not pressed
+    SkipIfKeyNotPressed { x: u8 },
+    /// F000 NNNN: I = NNNN, the address being the next 2 bytes (XO-CHIP)
+    LongAssignAddrToRamPointer,
+    /// F002: load the 16 bytes at I into the audio pattern buffer (XO-CHIP)
+    LoadAudioPattern,
+    /// FN01: select the bitplanes to draw on (XO-CHIP)
+    SelectPlanes { n: u8 },
+    /// FX07: VX = DT
+    AssignDelayTimerToReg { x: u8 },
+    /// FX0A: wait for a key press and store it in VX
+    WaitForKeyPress { x: u8 },
+    /// FX15: DT = VX
+    AssignRegToDelayTimer { x: u8 },
+    /// FX18: ST = VX
+    AssignRegToSoundTimer { x: u8 },
+    /// FX1E: I += VX
+    IncrementRamPointerByReg { x: u8 },
+    /// FX29: I = address of the font sprite for VX
+    SetRamPointerToFontAddr { x: u8 },
+    /// FX30: I = address of the big font sprite for VX (SCHIP)
+    SetRamPointerToBigFontAddr { x: u8 },
+    /// FX3A: set the audio pitch register to VX (XO-CHIP)
+    SetPitch { x: u8 },
+    /// FX33: store the BCD of VX at I, I+1, I+2
+    SetRamPointerToBcdOfReg { x: u8 },
+    /// FX55: store V0..VX into RAM starting at I
+    StoreRegsInRam { x: u8 },
+    /// FX65: load V0..VX from RAM starting at I
+    LoadRegsFromRam { x: u8 },
+    /// FX75: store V0..VX into the RPL user flags (SCHIP)
+    StoreRegsInFlags { x: u8 },
+    /// FX85: load V0..VX from the RPL user flags (SCHIP)
+    LoadRegsFromFlags { x: u8 },
+}
+
+/// Cowgod's assembly syntax, with the SUPER-CHIP and XO-CHIP extras.
+/// F000 shows as `LD I, LONG`, as its address is in the next 2 bytes.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Nop => write!(f, "NOP"),
+            Instruction::Sys { nnn } => write!(f, "SYS {:#05X}", nnn),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Jump { nnn } => write!(f, "JP {:#05X}", nnn),
+            Instruction::Call { nnn } => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SkipIfRegEqualsVal { x, nn } => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipIfRegNotEqualsVal { x, nn } => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            Instruction::SkipIfRegEqualsReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRegRangeInRam { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRegRangeFromRam { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::AssignValToReg { x, nn } => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            Instruction::AddValToReg { x, nn } => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            Instruction::AssignRegToReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::RegOrReg { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::RegAndReg { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::RegXorReg { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddRegToReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::SubRegToReg { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::RightShift { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::OppositeSubRegToReg { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::LeftShift { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfRegNotEqualsReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::AssignAddrToRamPointer { nnn } => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::JumpWithOffset { nnn } => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::AssignRandomToReg { x, nn } => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Instruction::DrawSprite { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipIfKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LongAssignAddrToRamPointer => write!(f, "LD I, LONG"),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::SelectPlanes { n } => write!(f, "PLANE {}", n),
+            Instruction::AssignDelayTimerToReg { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitForKeyPress { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::AssignRegToDelayTimer { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::AssignRegToSoundTimer { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::IncrementRamPointerByReg { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::SetRamPointerToFontAddr { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::SetRamPointerToBigFontAddr { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::SetPitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::SetRamPointerToBcdOfReg { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegsInRam { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegsFromRam { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreRegsInFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadRegsFromFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+/// The opcode doesn't match any known instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub op: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown opcode {:#06X}", self.op)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Splits the opcode into its four nibbles, and matches them
+/// against the CHIP-8 instruction set.
+pub fn decode(op: u16) -> Result<Instruction, DecodeError> {
+    let hex_digit1: u16 = (op & 0xF000) >> 12;
+    let hex_digit2: u16 = (op & 0x0F00) >> 8;
+    let hex_digit3: u16 = (op & 0x00F0) >> 4;
+    let hex_digit4: u16 = op & 0x000F;
+
+    let x: u8 = hex_digit2 as u8;
+    let y: u8 = hex_digit3 as u8;
+    let n: u8 = hex_digit4 as u8;
+    let nn: u8 = (op & 0xFF) as u8;
+    let nnn: u16 = op & 0xFFF;
+
+    let instruction = match (hex_digit1, hex_digit2, hex_digit3, hex_digit4) {
+        (0, 0, 0, 0) => Instruction::Nop,
+        (0, 0, 0xE, 0) => Instruction::ClearScreen,
+        (0, 0, 0xE, 0xE) => Instruction::Return,
+        (0, 0, 0xC, _) => Instruction::ScrollDown { n },
+        (0, 0, 0xD, _) => Instruction::ScrollUp { n },
+        (0, 0, 0xF, 0xB) => Instruction::ScrollRight,
+        (0, 0, 0xF, 0xC) => Instruction::ScrollLeft,
+        (0, 0, 0xF, 0xD) => Instruction::Exit,
+        (0, 0, 0xF, 0xE) => Instruction::LowRes,
+        (0, 0, 0xF, 0xF) => Instruction::HighRes,
+        (0, _, _, _) => Instruction::Sys { nnn },
+        (1, _, _, _) => Instruction::Jump { nnn },
+        (2, _, _, _) => Instruction::Call { nnn },
+        (3, _, _, _) => Instruction::SkipIfRegEqualsVal { x, nn },
+        (4, _, _, _) => Instruction::SkipIfRegNotEqualsVal { x, nn },
+        (5, _, _, 0) => Instruction::SkipIfRegEqualsReg { x, y },
+        (5, _, _, 2) => Instruction::StoreRegRangeInRam { x, y },
+        (5, _, _, 3) => Instruction::LoadRegRangeFromRam { x, y },
+        (6, _, _, _) => Instruction::AssignValToReg { x, nn },
+        (7, _, _, _) => Instruction::AddValToReg { x, nn },
+        (8, _, _, 0) => Instruction::AssignRegToReg { x, y },
+        (8, _, _, 1) => Instruction::RegOrReg { x, y },
+        (8, _, _, 2) => Instruction::RegAndReg { x, y },
+        (8, _, _, 3) => Instruction::RegXorReg { x, y },
+        (8, _, _, 4) => Instruction::AddRegToReg { x, y },
+        (8, _, _, 5) => Instruction::SubRegToReg { x, y },
+        (8, _, _, 6) => Instruction::RightShift { x, y },
+        (8, _, _, 7) => Instruction::OppositeSubRegToReg { x, y },
+        (8, _, _, 0xE) => Instruction::LeftShift { x, y },
+        (9, _, _, 0) => Instruction::SkipIfRegNotEqualsReg { x, y },
+        (0xA, _, _, _) => Instruction::AssignAddrToRamPointer { nnn },
+        (0xB, _, _, _) => Instruction::JumpWithOffset { nnn },
+        (0xC, _, _, _) => Instruction::AssignRandomToReg { x, nn },
+        (0xD, _, _, _) => Instruction::DrawSprite { x, y, n },
+        (0xE, _, 9, 0xE) => Instruction::SkipIfKeyPressed { x },
+        (0xE, _, 0xA, 1) => Instruction::SkipIfKeyNotPressed { x },
+        (0xF, 0, 0, 0) => Instruction::LongAssignAddrToRamPointer,
+        (0xF, _, 0, 1) => Instruction::SelectPlanes { n: x },
+        (0xF, 0, 0, 2) => Instruction::LoadAudioPattern,
+        (0xF, _, 0, 7) => Instruction::AssignDelayTimerToReg { x },
+        (0xF, _, 0, 0xA) => Instruction::WaitForKeyPress { x },
+        (0xF, _, 1, 5) => Instruction::AssignRegToDelayTimer { x },
+        (0xF, _, 1, 8) => Instruction::AssignRegToSoundTimer { x },
+        (0xF, _, 1, 0xE) => Instruction::IncrementRamPointerByReg { x },
+        (0xF, _, 2, 9) => Instruction::SetRamPointerToFontAddr { x },
+        (0xF, _, 3, 0) => Instruction::SetRamPointerToBigFontAddr { x },
+        (0xF, _, 3, 0xA) => Instruction::SetPitch { x },
+        (0xF, _, 3, 3) => Instruction::SetRamPointerToBcdOfReg { x },
+        (0xF, _, 5, 5) => Instruction::StoreRegsInRam { x },
+        (0xF, _, 6, 5) => Instruction::LoadRegsFromRam { x },
+        (0xF, _, 7, 5) => Instruction::StoreRegsInFlags { x },
+        (0xF, _, 8, 5) => Instruction::LoadRegsFromFlags { x },
+        (_, _, _, _) => return Err(DecodeError { op }),
+    };
+
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_operands_from_every_nibble() {
+        assert_eq!(decode(0x00E0), Ok(Instruction::ClearScreen));
+        assert_eq!(decode(0x1ABC), Ok(Instruction::Jump { nnn: 0xABC }));
+        assert_eq!(decode(0x3A42), Ok(Instruction::SkipIfRegEqualsVal { x: 0xA, nn: 0x42 }));
+        assert_eq!(decode(0x8127), Ok(Instruction::OppositeSubRegToReg { x: 1, y: 2 }));
+        assert_eq!(decode(0xD015), Ok(Instruction::DrawSprite { x: 0, y: 1, n: 5 }));
+        assert_eq!(decode(0xF229), Ok(Instruction::SetRamPointerToFontAddr { x: 2 }));
+    }
+
+    #[test]
+    fn decodes_super_chip_before_machine_code_calls() {
+        assert_eq!(decode(0x00C3), Ok(Instruction::ScrollDown { n: 3 }));
+        assert_eq!(decode(0x00FF), Ok(Instruction::HighRes));
+        assert_eq!(decode(0x00FD), Ok(Instruction::Exit));
+        assert_eq!(decode(0x0123), Ok(Instruction::Sys { nnn: 0x123 }));
+        assert_eq!(decode(0xF430), Ok(Instruction::SetRamPointerToBigFontAddr { x: 4 }));
+        assert_eq!(decode(0xF785), Ok(Instruction::LoadRegsFromFlags { x: 7 }));
+    }
+
+    #[test]
+    fn decodes_xo_chip_extensions() {
+        assert_eq!(decode(0x00D4), Ok(Instruction::ScrollUp { n: 4 }));
+        assert_eq!(decode(0x5AB2), Ok(Instruction::StoreRegRangeInRam { x: 0xA, y: 0xB }));
+        assert_eq!(decode(0x5BA3), Ok(Instruction::LoadRegRangeFromRam { x: 0xB, y: 0xA }));
+        assert_eq!(decode(0xF000), Ok(Instruction::LongAssignAddrToRamPointer));
+        assert_eq!(decode(0xF301), Ok(Instruction::SelectPlanes { n: 3 }));
+        assert_eq!(decode(0xF002), Ok(Instruction::LoadAudioPattern));
+        assert_eq!(decode(0xF53A), Ok(Instruction::SetPitch { x: 5 }));
+    }
+
+    #[test]
+    fn formats_cowgod_mnemonics() {
+        let mnemonic = |op: u16| decode(op).unwrap().to_string();
+        assert_eq!(mnemonic(0x6120), "LD V1, 0x20");
+        assert_eq!(mnemonic(0xD015), "DRW V0, V1, 5");
+        assert_eq!(mnemonic(0x2ABC), "CALL 0xABC");
+        assert_eq!(mnemonic(0x8AB6), "SHR VA, VB");
+        assert_eq!(mnemonic(0xF355), "LD [I], V3");
+        assert_eq!(mnemonic(0x00C2), "SCD 2");
+    }
+
+    #[test]
+    fn rejects_unknown_opcodes() {
+        assert_eq!(decode(0x5121), Err(DecodeError { op: 0x5121 }));
+        assert_eq!(decode(0xF100), Err(DecodeError { op: 0xF100 }));
+        assert_eq!(decode(0x812F), Err(DecodeError { op: 0x812F }));
+        assert_eq!(decode(0xFFFF), Err(DecodeError { op: 0xFFFF }));
+    }
+}
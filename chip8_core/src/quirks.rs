@@ -0,0 +1,122 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// What FX55/FX65 do with I once the registers are copied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left untouched
+    Unchanged,
+    /// I += X
+    X,
+    /// I += X + 1
+    XPlusOne,
+}
+
+/// Toggles for the instructions whose behavior changed between
+/// CHIP-8 interpreters. ROMs written for one platform may rely on
+/// it, so pick the preset matching the ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY and store the result in VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    /// How FX55/FX65 move I
+    pub load_store_increment: IndexIncrement,
+    /// DXYN clips sprites at the screen edges instead of wrapping them around
+    pub clip_sprites: bool,
+    /// 8XY1/8XY2/8XY3 set VF to 0
+    pub logic_resets_vf: bool,
+    /// BNNN behaves like BXNN, jumping to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+}
+
+impl Quirks {
+    /// The original interpreter, running on the RCA COSMAC VIP
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increment: IndexIncrement::XPlusOne,
+            clip_sprites: true,
+            logic_resets_vf: true,
+            jump_uses_vx: false,
+        }
+    }
+
+    /// The HP-48 calculators interpreter
+    pub fn chip48() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increment: IndexIncrement::X,
+            clip_sprites: true,
+            logic_resets_vf: false,
+            jump_uses_vx: true,
+        }
+    }
+
+    /// SUPER-CHIP 1.1, also for the HP-48
+    pub fn super_chip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            load_store_increment: IndexIncrement::Unchanged,
+            clip_sprites: true,
+            logic_resets_vf: false,
+            jump_uses_vx: true,
+        }
+    }
+
+    /// XO-CHIP, as implemented by Octo
+    pub fn xo_chip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            load_store_increment: IndexIncrement::XPlusOne,
+            clip_sprites: false,
+            logic_resets_vf: false,
+            jump_uses_vx: false,
+        }
+    }
+
+    /// The preset for a command line name: vip, chip48, schip or xochip
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vip" => Some(Self::cosmac_vip()),
+            "chip48" => Some(Self::chip48()),
+            "schip" => Some(Self::super_chip()),
+            "xochip" => Some(Self::xo_chip()),
+            _ => None,
+        }
+    }
+}
+
+impl Quirks {
+
+    pub(crate) fn save(&self, writer: &mut StateWriter) {
+        writer.bool(self.shift_uses_vy);
+        writer.u8(match self.load_store_increment {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::X => 1,
+            IndexIncrement::XPlusOne => 2,
+        });
+        writer.bool(self.clip_sprites);
+        writer.bool(self.logic_resets_vf);
+        writer.bool(self.jump_uses_vx);
+    }
+
+    pub(crate) fn restore(reader: &mut StateReader) -> Result<Self, StateError> {
+        Ok(Self {
+            shift_uses_vy: reader.bool("quirks")?,
+            load_store_increment: match reader.u8()? {
+                0 => IndexIncrement::Unchanged,
+                1 => IndexIncrement::X,
+                2 => IndexIncrement::XPlusOne,
+                _ => return Err(StateError::InvalidValue { field: "quirks" }),
+            },
+            clip_sprites: reader.bool("quirks")?,
+            logic_resets_vf: reader.bool("quirks")?,
+            jump_uses_vx: reader.bool("quirks")?,
+        })
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::cosmac_vip()
+    }
+}
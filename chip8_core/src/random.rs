@@ -0,0 +1,65 @@
+/// Where CXNN gets its random bytes from. Implement it to feed the
+/// emulator a scripted or recorded sequence. Sources are Send, so that
+/// emulators can be moved to other threads.
+pub trait RandomSource: Send {
+    fn next_byte(&mut self) -> u8;
+
+    /// Everything needed to resume the sequence, stored in save states
+    fn save(&self) -> Vec<u8>;
+
+    /// Resumes the sequence from a save() output.
+    /// Returns false, leaving the source untouched, if the state isn't valid.
+    fn restore(&mut self, state: &[u8]) -> bool;
+}
+
+/// xorshift64* generator, the default RandomSource.
+/// The same seed always produces the same sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+
+    /// A zero seed would get the generator stuck, so it's replaced
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.set_state(seed);
+        rng
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: u64) {
+        self.state = if state == 0 { 0x9E37_79B9_7F4A_7C15 } else { state };
+    }
+}
+
+impl RandomSource for Xorshift {
+    fn next_byte(&mut self) -> u8 {
+        Xorshift::next_byte(self)
+    }
+
+    fn save(&self) -> Vec<u8> {
+        self.state.to_be_bytes().to_vec()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> bool {
+        match <[u8; 8]>::try_from(state) {
+            Ok(bytes) => {
+                self.set_state(u64::from_be_bytes(bytes));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
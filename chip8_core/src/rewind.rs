@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+
+/// Ring buffer of save states, taken every `interval` frames.
+/// Once full, the oldest snapshot makes room for the newest one.
+///
+/// Snapshots are mostly untouched RAM and blank screen, so they are
+/// run-length encoded, taking a few KB each instead of ~72 KB.
+pub struct RewindBuffer {
+    snapshots: VecDeque<Vec<u8>>,
+    capacity: usize,
+    interval: usize,
+    frames: usize, // Frames since the last snapshot
+    current: bool, // The newest snapshot is the state the emulator is in
+}
+
+impl RewindBuffer {
+
+    /// Holds up to capacity snapshots, so capacity * interval frames
+    pub fn new(capacity: usize, interval: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            interval: interval.max(1),
+            frames: 0,
+            current: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.frames = 0;
+        self.current = false;
+    }
+
+    /// Counts a frame, and returns true when it's time for a snapshot
+    pub fn frame_done(&mut self) -> bool {
+        self.current = false;
+        self.frames += 1;
+        if self.frames < self.interval {
+            return false;
+        }
+        self.frames = 0;
+        true
+    }
+
+    pub fn push(&mut self, state: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(compress(state));
+        self.current = true;
+    }
+
+    /// The emulator state was replaced, by something else than pop
+    pub fn state_replaced(&mut self) {
+        self.current = false;
+    }
+
+    /// Takes out the snapshot steps snapshots back from the current
+    /// state, dropping the newer ones. When the newest snapshot was just
+    /// taken, it's the current state and doesn't count as a step.
+    /// Past the oldest snapshot, the oldest one is returned.
+    pub fn pop(&mut self, steps: usize) -> Option<Vec<u8>> {
+        let steps: usize = steps.max(1) + self.current as usize;
+        let keep: usize = self.snapshots.len().saturating_sub(steps);
+        self.snapshots.truncate(keep + 1);
+        self.frames = 0;
+        self.current = false;
+        self.snapshots.pop_back().map(|snapshot| decompress(&snapshot))
+    }
+}
+
+/// PackBits: a header byte n followed by n + 1 literal bytes (n < 128),
+/// or by a single byte repeated 257 - n times (n > 128).
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len() / 8);
+    let mut idx: usize = 0;
+
+    while idx < data.len() {
+        let run: usize = data[idx..].iter().take(128).take_while(|&&byte| byte == data[idx]).count();
+
+        if run >= 2 {
+            out.push((257 - run) as u8);
+            out.push(data[idx]);
+            idx += run;
+        } else {
+            // Literals up to the next run of at least 2
+            let mut end: usize = idx + 1;
+            while end < data.len() && end - idx < 128 && (end + 1 >= data.len() || data[end] != data[end + 1]) {
+                end += 1;
+            }
+            out.push((end - idx - 1) as u8);
+            out.extend_from_slice(&data[idx..end]);
+            idx = end;
+        }
+    }
+
+    out
+}
+
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::new();
+    let mut idx: usize = 0;
+
+    while idx < data.len() {
+        let header: usize = data[idx] as usize;
+        if header < 128 {
+            out.extend_from_slice(&data[idx + 1..idx + 2 + header]);
+            idx += 2 + header;
+        } else {
+            out.extend(std::iter::repeat_n(data[idx + 1], 257 - header));
+            idx += 2;
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression_round_trips() {
+        let mut data: Vec<u8> = vec![0; 1000];
+        data.extend([1, 2, 3, 3, 4, 5, 5, 5]);
+        data.extend((0..=255).collect::<Vec<u8>>());
+        data.push(9);
+
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len() / 2);
+        assert_eq!(decompress(&compressed), data);
+        assert_eq!(decompress(&compress(&[7])), vec![7]);
+    }
+
+    #[test]
+    fn oldest_snapshots_make_room() {
+        let mut buffer = RewindBuffer::new(3, 2);
+        for frame in 0..10u8 {
+            if buffer.frame_done() {
+                buffer.push(&[frame]);
+            }
+        }
+
+        // Snapshots from frames 5, 7 and 9, the last one being the current state
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop(1), Some(vec![7]));
+        assert_eq!(buffer.pop(5), Some(vec![5]));
+        assert_eq!(buffer.pop(1), None);
+    }
+
+    #[test]
+    fn older_snapshots_are_a_step_back() {
+        let mut buffer = RewindBuffer::new(3, 2);
+        for frame in 0..4u8 {
+            if buffer.frame_done() {
+                buffer.push(&[frame]);
+            }
+        }
+        // Frame 4 ran since the snapshot of frame 3
+        buffer.frame_done();
+        assert_eq!(buffer.pop(1), Some(vec![3]));
+    }
+}
@@ -0,0 +1,201 @@
+use crate::emulator::{HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::state::{StateError, StateReader, StateWriter};
+
+/// Number of XO-CHIP drawing bitplanes
+pub const NUM_PLANES: usize = 2;
+
+/// The framebuffer. It is always big enough for the SUPER-CHIP
+/// high resolution mode, but only the first width * height pixels
+/// are in use, laid out row after row.
+///
+/// Every pixel holds one bit per XO-CHIP bitplane, so its value
+/// (0 to 3) is the palette index a frontend should paint it with.
+#[derive(Clone)]
+pub struct Screen {
+    hires: bool,
+    planes: u8, // Bitmask of the planes drawing and clearing act on
+    pixels: [u8; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Screen {
+
+    pub fn new() -> Self {
+        Self {
+            hires: false,
+            planes: 1,
+            pixels: [0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_SCREEN_WIDTH } else { SCREEN_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_SCREEN_HEIGHT } else { SCREEN_HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// The pixel at (x, y) is pixels()[x + width() * y].
+    /// Bit N is set if the pixel is lit on plane N.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels[..self.width() * self.height()]
+    }
+
+    /// Switching resolution also clears every plane
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.pixels = [0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT];
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Only the lowest NUM_PLANES bits of the mask are kept
+    pub fn select_planes(&mut self, mask: u8) {
+        self.planes = mask & ((1 << NUM_PLANES) - 1);
+    }
+
+    /// Clears the selected planes
+    pub fn clear(&mut self) {
+        let keep: u8 = !self.planes;
+        self.pixels.iter_mut().for_each(|p| *p &= keep);
+    }
+
+    /// Flips the pixel on the given plane (a single bit mask), and
+    /// returns true if it was turned off (a collision)
+    pub fn flip(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let idx: usize = x + self.width() * y;
+        let was_set: bool = self.pixels[idx] & plane != 0;
+        self.pixels[idx] ^= plane;
+        was_set
+    }
+
+    pub(crate) fn save(&self, writer: &mut StateWriter) {
+        writer.bool(self.hires);
+        writer.u8(self.planes);
+        writer.bytes(&self.pixels);
+    }
+
+    pub(crate) fn restore(reader: &mut StateReader) -> Result<Self, StateError> {
+        let max_pixel: u8 = (1 << NUM_PLANES) - 1;
+        let hires: bool = reader.bool("resolution")?;
+        let planes: u8 = reader.u8()?;
+        let pixels = reader.array::<{ HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT }>()?;
+
+        if planes > max_pixel {
+            return Err(StateError::InvalidValue { field: "planes" });
+        }
+        if pixels.iter().any(|&p| p > max_pixel) {
+            return Err(StateError::InvalidValue { field: "screen" });
+        }
+        Ok(Self { hires, planes, pixels })
+    }
+
+    /// Moves the pixel at src to dst, on the selected planes only
+    fn move_pixel(&mut self, src: Option<usize>, dst: usize) {
+        let planes: u8 = self.planes;
+        let moved: u8 = src.map_or(0, |src| self.pixels[src] & planes);
+        self.pixels[dst] = (self.pixels[dst] & !planes) | moved;
+    }
+
+    /// Moves every row n pixels down, the top rows are left blank
+    pub fn scroll_down(&mut self, n: usize) {
+        let len: usize = self.width() * self.height();
+        let shift: usize = (self.width() * n).min(len);
+
+        for dst in (0..len).rev() {
+            self.move_pixel(dst.checked_sub(shift), dst);
+        }
+    }
+
+    /// Moves every row n pixels up, the bottom rows are left blank
+    pub fn scroll_up(&mut self, n: usize) {
+        let len: usize = self.width() * self.height();
+        let shift: usize = (self.width() * n).min(len);
+
+        for dst in 0..len {
+            self.move_pixel(Some(dst + shift).filter(|&src| src < len), dst);
+        }
+    }
+
+    /// Moves every column n pixels right, the leftmost columns are left blank
+    pub fn scroll_right(&mut self, n: usize) {
+        let width: usize = self.width();
+
+        for row in 0..self.height() {
+            for x in (0..width).rev() {
+                let src: Option<usize> = x.checked_sub(n).map(|src| src + row * width);
+                self.move_pixel(src, x + row * width);
+            }
+        }
+    }
+
+    /// Moves every column n pixels left, the rightmost columns are left blank
+    pub fn scroll_left(&mut self, n: usize) {
+        let width: usize = self.width();
+
+        for row in 0..self.height() {
+            for x in 0..width {
+                let src: Option<usize> = Some(x + n).filter(|&src| src < width).map(|src| src + row * width);
+                self.move_pixel(src, x + row * width);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrolling_moves_pixels_and_blanks_the_gap() {
+        let mut screen = Screen::new();
+        screen.flip(0, 0, 1);
+
+        screen.scroll_down(2);
+        screen.scroll_right(4);
+        assert_eq!(screen.pixels().iter().position(|&p| p != 0), Some(4 + SCREEN_WIDTH * 2));
+
+        screen.scroll_left(4);
+        screen.scroll_up(1);
+        assert_eq!(screen.pixels().iter().position(|&p| p != 0), Some(SCREEN_WIDTH));
+
+        screen.scroll_left(4);
+        assert!(screen.pixels().iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn resolution_switch_resizes_and_clears() {
+        let mut screen = Screen::new();
+        screen.flip(3, 3, 1);
+
+        screen.set_hires(true);
+        assert_eq!(screen.pixels().len(), HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT);
+        assert!(screen.pixels().iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn clearing_and_scrolling_only_touch_the_selected_planes() {
+        let mut screen = Screen::new();
+        screen.flip(0, 0, 1);
+        screen.flip(0, 0, 2);
+
+        screen.select_planes(2);
+        screen.scroll_right(1);
+        assert_eq!(&screen.pixels()[..2], &[1, 2]);
+
+        screen.clear();
+        assert_eq!(&screen.pixels()[..2], &[1, 0]);
+    }
+}
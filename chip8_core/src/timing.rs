@@ -0,0 +1,124 @@
+use crate::emulator::NUM_VREGS;
+use crate::operations::Instruction;
+
+/// Machine cycles (8 clock cycles of the 1.76 MHz CDP1802) in a 60 Hz frame
+pub const CYCLES_PER_FRAME: usize = 3668;
+
+/// What's left of a frame for the interpreter, once the CDP1861 display
+/// has taken its 128 lines of 8 DMA cycles and the interrupt routine has
+/// counted the timers down
+pub const INTERPRETER_CYCLES_PER_FRAME: usize = CYCLES_PER_FRAME - 1024 - 46;
+
+/// Fetching an instruction and jumping to its routine, paid by all of them
+const FETCH_CYCLES: usize = 40;
+
+/// Setting up a sprite before its first row
+const DRAW_CYCLES: usize = 54;
+/// Each sprite row, plus 4 per bit it's shifted by to line up with x,
+/// plus ROW_SPLIT_CYCLES when it straddles two display bytes
+const ROW_CYCLES: usize = 26;
+const ROW_SPLIT_CYCLES: usize = 12;
+
+/// The machine cycles the COSMAC VIP interpreter spends on an instruction,
+/// v_reg being the registers before it runs. Waiting for the vertical blank
+/// before drawing is up to the caller, only the drawing itself is counted.
+///
+/// The figures are rounded from timings of the VIP interpreter's routines.
+/// Instructions the VIP doesn't have cost as much as a jump.
+pub fn cycles(instruction: Instruction, v_reg: &[u8; NUM_VREGS]) -> usize {
+    let routine: usize = match instruction {
+        Instruction::ClearScreen => 638,
+        Instruction::AssignValToReg { .. } => 2,
+        Instruction::AddValToReg { .. }
+        | Instruction::AssignDelayTimerToReg { .. }
+        | Instruction::AssignRegToDelayTimer { .. }
+        | Instruction::AssignRegToSoundTimer { .. }
+        | Instruction::WaitForKeyPress { .. } => 6,
+        Instruction::SkipIfRegEqualsVal { .. }
+        | Instruction::SkipIfRegNotEqualsVal { .. }
+        | Instruction::AssignAddrToRamPointer { .. } => 8,
+        Instruction::SkipIfRegEqualsReg { .. }
+        | Instruction::SkipIfRegNotEqualsReg { .. }
+        | Instruction::SkipIfKeyPressed { .. }
+        | Instruction::SkipIfKeyNotPressed { .. } => 12,
+        Instruction::IncrementRamPointerByReg { .. } => 15,
+        Instruction::SetRamPointerToFontAddr { .. } => 16,
+        Instruction::AssignRandomToReg { .. } => 32,
+        Instruction::AssignRegToReg { .. }
+        | Instruction::RegOrReg { .. }
+        | Instruction::RegAndReg { .. }
+        | Instruction::RegXorReg { .. }
+        | Instruction::AddRegToReg { .. }
+        | Instruction::SubRegToReg { .. }
+        | Instruction::RightShift { .. }
+        | Instruction::OppositeSubRegToReg { .. }
+        | Instruction::LeftShift { .. } => 40,
+        Instruction::SetRamPointerToBcdOfReg { .. } => 200,
+        // One loop round per register
+        Instruction::StoreRegsInRam { x } | Instruction::LoadRegsFromRam { x } => 17 + 14 * (x as usize + 1),
+        Instruction::DrawSprite { x, n, .. } => {
+            let shift: usize = v_reg[x as usize] as usize % 8;
+            let split: usize = if shift > 0 { ROW_SPLIT_CYCLES } else { 0 };
+            // DXY0 draws 16 rows of 2 bytes on the SUPER-CHIP, as much work as 32 rows
+            let rows: usize = if n == 0 { 32 } else { n as usize };
+            DRAW_CYCLES + rows * (ROW_CYCLES + 4 * shift + split)
+        }
+        _ => 19,
+    };
+
+    FETCH_CYCLES + routine
+}
+
+/// Cycles a frame's instructions ran past its end, taken from the next one
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VipTiming {
+    overrun: usize,
+}
+
+impl VipTiming {
+
+    pub fn new() -> Self {
+        Self { overrun: 0 }
+    }
+
+    /// Cycles already used when a frame starts
+    pub fn overrun(&self) -> usize {
+        self.overrun
+    }
+
+    /// Carries whatever spent goes past the frame's budget into the next frame
+    pub fn end_frame(&mut self, spent: usize) {
+        self.overrun = spent.saturating_sub(INTERPRETER_CYCLES_PER_FRAME);
+    }
+
+    pub fn clear(&mut self) {
+        self.overrun = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_cost_more_rows_and_misalignment() {
+        let mut v_reg: [u8; NUM_VREGS] = [0; NUM_VREGS];
+        let draw = |n: u8, v_reg: &[u8; NUM_VREGS]| cycles(Instruction::DrawSprite { x: 0, y: 1, n }, v_reg);
+
+        let aligned: usize = draw(5, &v_reg);
+        assert!(draw(10, &v_reg) > aligned);
+        v_reg[0] = 3;
+        assert!(draw(5, &v_reg) > aligned);
+        v_reg[0] = 8;
+        assert_eq!(draw(5, &v_reg), aligned);
+    }
+
+    #[test]
+    fn overrun_carries_into_the_next_frame_only() {
+        let mut timing = VipTiming::new();
+        timing.end_frame(INTERPRETER_CYCLES_PER_FRAME + 30);
+        assert_eq!(timing.overrun(), 30);
+        timing.end_frame(INTERPRETER_CYCLES_PER_FRAME - 10);
+        assert_eq!(timing.overrun(), 0);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::emulator::{NUM_VREGS, STACK_SIZE};
+use crate::error::EmulatorError;
+use crate::operations::Instruction;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// The registers an instruction can change, besides the pc
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; NUM_VREGS],
+    pub i: u16,
+    pub sp: u16,
+    pub stack: [u16; STACK_SIZE],
+    pub dt: u8,
+    pub st: u8,
+}
+
+/// `V0:00 .. VF:00 I:0000 SP:00 DT:00 ST:00 STACK:[0000 ..]`,
+/// the stack listing only the sp entries in use, oldest first
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, value) in self.v.iter().enumerate() {
+            write!(f, "V{:X}:{:02X} ", idx, value)?;
+        }
+        write!(f, "I:{:04X} SP:{:02X} DT:{:02X} ST:{:02X} STACK:[", self.i, self.sp, self.dt, self.st)?;
+        let used: usize = (self.sp as usize).min(STACK_SIZE);
+        for (idx, addr) in self.stack[..used].iter().enumerate() {
+            let separator: &str = if idx == 0 { "" } else { " " };
+            write!(f, "{}{:04X}", separator, addr)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// A single executed instruction, or one that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub op: u16,
+    /// None if op doesn't decode
+    pub instruction: Option<Instruction>,
+    pub before: Registers,
+    pub after: Registers,
+    /// Why the instruction failed, after then being what it left behind
+    pub error: Option<EmulatorError>,
+}
+
+/// One line per instruction: address, opcode and mnemonic in fixed width
+/// columns, the registers before the instruction runs, then after it.
+/// A failed instruction ends with its error instead, and undecodable
+/// opcodes show ??? as their mnemonic.
+///
+/// `0206  8014  ADD V0, V1          V0:05 .. STACK:[] -> V0:0B .. STACK:[]`
+/// `0208  FFFF  ???                 V0:0B .. STACK:[] -> error: unknown opcode 0xFFFF at 0x208`
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic: String = self.instruction.map_or_else(|| "???".to_string(), |instruction| instruction.to_string());
+        write!(f, "{:04X}  {:04X}  {:<20}{} -> ", self.pc, self.op, mnemonic, self.before)?;
+        match self.error {
+            Some(err) => write!(f, "error: {}", err),
+            None => write!(f, "{}", self.after),
+        }
+    }
+}
+
+/// Which instructions get traced. The default traces everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only instructions at these addresses
+    pub pcs: Option<RangeInclusive<u16>>,
+    /// Only these opcode classes, bit N standing for the NXXX opcodes
+    pub classes: Option<u16>,
+}
+
+impl TraceFilter {
+
+    pub fn matches(&self, pc: u16, op: u16) -> bool {
+        let class: u16 = op >> 12;
+        self.pcs.as_ref().is_none_or(|pcs| pcs.contains(&pc))
+            && self.classes.is_none_or(|classes| classes & (1 << class) != 0)
+    }
+}
+
+/// Where the trace goes
+pub enum TraceSink {
+    /// One line per entry
+    Writer(Box<dyn Write + Send>),
+    /// The last capacity entries, oldest first
+    Ring { entries: VecDeque<TraceEntry>, capacity: usize },
+}
+
+impl TraceSink {
+
+    pub fn file(path: &Path) -> io::Result<Self> {
+        Ok(Self::Writer(Box::new(BufWriter::new(File::create(path)?))))
+    }
+
+    pub fn stderr() -> Self {
+        Self::Writer(Box::new(io::stderr()))
+    }
+
+    pub fn ring(capacity: usize) -> Self {
+        Self::Ring { entries: VecDeque::with_capacity(capacity), capacity }
+    }
+}
+
+/// Records the instructions the emulator runs, see Emulator::enable_trace
+pub struct Tracer {
+    sink: TraceSink,
+    filter: TraceFilter,
+}
+
+impl Tracer {
+
+    pub fn new(sink: TraceSink, filter: TraceFilter) -> Self {
+        Self { sink, filter }
+    }
+
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: TraceFilter) {
+        self.filter = filter;
+    }
+
+    /// The recorded entries, if tracing to a ring buffer
+    pub fn entries(&self) -> Option<&VecDeque<TraceEntry>> {
+        match &self.sink {
+            TraceSink::Ring { entries, .. } => Some(entries),
+            TraceSink::Writer(_) => None,
+        }
+    }
+
+    pub(crate) fn wants(&self, pc: u16, op: u16) -> bool {
+        self.filter.matches(pc, op)
+    }
+
+    pub(crate) fn record(&mut self, entry: TraceEntry) {
+        match &mut self.sink {
+            // A failing sink shouldn't stop the emulation, so errors are dropped
+            TraceSink::Writer(writer) => {
+                let _ = writeln!(writer, "{}", entry);
+            }
+            TraceSink::Ring { entries, capacity } => {
+                if *capacity == 0 {
+                    return;
+                }
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                entries.push_back(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use std::sync::{Arc, Mutex};
+
+    /// V0 = 5, V1 = 6, I = 0x300, CALL 0x20C, then JP 0x20A forever.
+    /// 0x20C: V0 += V1 and RET
+    const ROM: [u8; 16] = [
+        0x60, 0x05, 0x61, 0x06, 0xA3, 0x00, 0x22, 0x0C, 0x00, 0x00, 0x12, 0x0A, 0x80, 0x14, 0x00, 0xEE,
+    ];
+
+    fn run(tracer: Tracer) -> Emulator {
+        let mut emulator = Emulator::default();
+        emulator.load(&ROM).unwrap();
+        emulator.enable_trace(tracer);
+        for _ in 0..6 {
+            emulator.tick().unwrap();
+        }
+        emulator
+    }
+
+    /// Lets the test read what went into a Writer sink
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_a_line_per_instruction() {
+        let output: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        run(Tracer::new(TraceSink::Writer(Box::new(Shared(output.clone()))), TraceFilter::default()));
+
+        let text: String = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(
+            lines[4],
+            "020C  8014  ADD V0, V1          \
+             V0:05 V1:06 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 VE:00 VF:00 \
+             I:0300 SP:01 DT:00 ST:00 STACK:[0208] -> \
+             V0:0B V1:06 V2:00 V3:00 V4:00 V5:00 V6:00 V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 VE:00 VF:00 \
+             I:0300 SP:01 DT:00 ST:00 STACK:[0208]"
+        );
+        assert!(lines[5].starts_with("020E  00EE  RET                 V0:0B "));
+        assert!(lines[5].ends_with(" SP:01 DT:00 ST:00 STACK:[0208] -> V0:0B V1:06 V2:00 V3:00 V4:00 V5:00 V6:00 \
+                                     V7:00 V8:00 V9:00 VA:00 VB:00 VC:00 VD:00 VE:00 VF:00 I:0300 SP:00 DT:00 ST:00 STACK:[]"));
+    }
+
+    #[test]
+    fn traces_failed_instructions() {
+        let output: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut emulator = Emulator::default();
+        // V0 = 5, then an opcode that doesn't decode
+        emulator.load(&[0x60, 0x05, 0xFF, 0xFF]).unwrap();
+        emulator.enable_trace(Tracer::new(TraceSink::Writer(Box::new(Shared(output.clone()))), TraceFilter::default()));
+        emulator.tick().unwrap();
+        assert!(emulator.tick().is_err());
+
+        let text: String = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("0202  FFFF  ???                 V0:05 "));
+        assert!(lines[1].ends_with(" STACK:[] -> error: unknown opcode 0xFFFF at 0x202"));
+    }
+
+    #[test]
+    fn ring_buffer_keeps_the_last_filtered_entries() {
+        let filter = TraceFilter { pcs: Some(0x202..=0x20C), classes: Some(1 << 0x6 | 1 << 0x8) };
+        let emulator = run(Tracer::new(TraceSink::ring(2), filter));
+
+        let entries = emulator.tracer().unwrap().entries().unwrap();
+        let pcs: Vec<u16> = entries.iter().map(|entry| entry.pc).collect();
+        assert_eq!(pcs, [0x202, 0x20C]);
+        assert_eq!((entries[1].before.v[0], entries[1].after.v[0]), (5, 11));
+    }
+}
@@ -0,0 +1,235 @@
+//! Runs random programs through Emulator and the reference interpreter
+//! in tests/reference, comparing the whole machine after every
+//! instruction, and feeds tick() arbitrary bytes to make sure it never
+//! panics. Programs also run with and without the decode cache and the
+//! recompiler, which must not change anything.
+//!
+//! PROPTEST_CASES=100000 cargo test --release --test differential
+//! runs a longer session.
+
+mod reference;
+
+use chip8_core::emulator::{Emulator, START_ADDR};
+use chip8_core::quirks::Quirks;
+use proptest::prelude::*;
+use reference::{Reference, Step, HEIGHT, WIDTH};
+
+/// Opcode templates, as (operand mask, fixed bits), covering all of CHIP-8
+const TEMPLATES: &[(u16, u16)] = &[
+    (0x0000, 0x00E0), (0x0000, 0x00EE), (0x0FFF, 0x1000), (0x0FFF, 0x2000),
+    (0x0FFF, 0x3000), (0x0FFF, 0x4000), (0x0FF0, 0x5000), (0x0FFF, 0x6000),
+    (0x0FFF, 0x7000), (0x0FF0, 0x8000), (0x0FF0, 0x8001), (0x0FF0, 0x8002),
+    (0x0FF0, 0x8003), (0x0FF0, 0x8004), (0x0FF0, 0x8005), (0x0FF0, 0x8006),
+    (0x0FF0, 0x8007), (0x0FF0, 0x800E), (0x0FF0, 0x9000), (0x0FFF, 0xA000),
+    (0x0FFF, 0xB000), (0x0FFF, 0xC000), (0x0FFF, 0xD000), (0x0F00, 0xE09E),
+    (0x0F00, 0xE0A1), (0x0F00, 0xF007), (0x0F00, 0xF00A), (0x0F00, 0xF015),
+    (0x0F00, 0xF018), (0x0F00, 0xF01E), (0x0F00, 0xF029), (0x0F00, 0xF033),
+    (0x0F00, 0xF055), (0x0F00, 0xF065),
+];
+
+/// Most instructions run per program
+const MAX_TICKS: usize = 200;
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    prop_oneof![
+        Just(Quirks::cosmac_vip()),
+        Just(Quirks::chip48()),
+        Just(Quirks::super_chip()),
+        Just(Quirks::xo_chip()),
+    ]
+}
+
+/// Mostly CHIP-8 instructions, with a few raw words mixed in.
+/// Jumps and calls mostly land inside the program, on an instruction,
+/// and ANNN points inside it too so that FX55 and FX33 overwrite code.
+fn program() -> impl Strategy<Value = Vec<u8>> {
+    let instruction = prop_oneof![
+        9 => (0..TEMPLATES.len(), any::<u16>()).prop_map(|(idx, operands)| {
+            let (mask, fixed) = TEMPLATES[idx];
+            fixed | (operands & mask)
+        }),
+        1 => any::<u16>(),
+    ];
+
+    prop::collection::vec(instruction, 1..64).prop_map(|mut ops| {
+        let len: u16 = ops.len() as u16;
+        for op in ops.iter_mut() {
+            if matches!(*op >> 12, 0x1 | 0x2 | 0xA) {
+                *op = (*op & 0xF000) | (START_ADDR + (*op % len) * 2);
+            }
+        }
+        ops.iter().flat_map(|op| op.to_be_bytes()).collect()
+    })
+}
+
+/// The registers both interpreters start with
+#[derive(Debug, Clone)]
+struct Start {
+    v: [u8; 16],
+    i: u16,
+    dt: u8,
+    st: u8,
+    keys: [bool; 16],
+    seed: u64,
+}
+
+fn start() -> impl Strategy<Value = Start> {
+    (any::<[u8; 16]>(), any::<u16>(), any::<u8>(), any::<u8>(), any::<[bool; 16]>(), any::<u64>())
+        .prop_map(|(v, i, dt, st, keys, seed)| Start { v, i, dt, st, keys, seed })
+}
+
+/// What differs between the two, if anything
+fn compare(emulator: &Emulator, reference: &Reference) -> Result<(), String> {
+    let sp: usize = emulator.sp() as usize;
+    let checks: [(&str, bool); 9] = [
+        ("pc", emulator.pc() == reference.pc),
+        ("V registers", emulator.v_reg() == &reference.v),
+        ("I", emulator.i_reg() == reference.i),
+        ("stack", emulator.stack()[..sp] == reference.stack[..]),
+        ("DT", emulator.dt() == reference.dt),
+        ("ST", emulator.st() == reference.st),
+        ("RAM", emulator.ram() == &reference.ram[..]),
+        ("screen size", emulator.get_display().pixels().len() == WIDTH * HEIGHT),
+        (
+            "screen",
+            emulator.get_display().pixels().iter().zip(reference.screen).all(|(&a, b)| (a != 0) == b),
+        ),
+    ];
+
+    match checks.iter().find(|(_, same)| !same) {
+        Some((name, _)) => Err(format!(
+            "{} differs: pc {:#06X} vs {:#06X}, V {:02X?} vs {:02X?}, I {:#06X} vs {:#06X}",
+            name,
+            emulator.pc(),
+            reference.pc,
+            emulator.v_reg(),
+            reference.v,
+            emulator.i_reg(),
+            reference.i
+        )),
+        None => Ok(()),
+    }
+}
+
+proptest! {
+    #[test]
+    fn emulator_matches_the_reference(quirks in quirks(), rom in program(), start in start(), cached in any::<bool>()) {
+        let mut emulator = Emulator::with_seed(quirks, start.seed);
+        if cached {
+            emulator.enable_decode_cache();
+        }
+        emulator.load(&rom).unwrap();
+        let mut reference = Reference::new(quirks, start.seed, emulator.ram());
+
+        for idx in 0..16 {
+            emulator.set_v_reg(idx, start.v[idx]);
+            emulator.keypress(idx, start.keys[idx]);
+        }
+        emulator.set_i_reg(start.i);
+        emulator.set_dt(start.dt);
+        emulator.set_st(start.st);
+        reference.v = start.v;
+        reference.i = start.i;
+        reference.dt = start.dt;
+        reference.st = start.st;
+        reference.keys = start.keys;
+
+        for tick in 0..MAX_TICKS {
+            let pc: u16 = reference.pc;
+            match reference.step() {
+                Step::Unsupported(_) => break,
+                Step::Done => prop_assert_eq!(emulator.tick(), Ok(()), "tick {} at {:#06X}", tick, pc),
+                Step::Failed(err) => {
+                    prop_assert_eq!(emulator.tick(), Err(err), "tick {} at {:#06X}", tick, pc);
+                    break;
+                }
+            }
+            if let Err(difference) = compare(&emulator, &reference) {
+                let op: u16 = u16::from_be_bytes([reference.ram[pc as usize], reference.ram[pc as usize + 1]]);
+                prop_assert!(false, "after {:04X} at {:#06X}, tick {}: {}", op, pc, tick, difference);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_cache_changes_nothing(quirks in quirks(), rom in program(), start in start()) {
+        let mut emulators: Vec<Emulator> = (0..2).map(|_| Emulator::with_seed(quirks, start.seed)).collect();
+        emulators[1].enable_decode_cache();
+        for emulator in emulators.iter_mut() {
+            emulator.load(&rom).unwrap();
+            for idx in 0..16 {
+                emulator.set_v_reg(idx, start.v[idx]);
+                emulator.keypress(idx, start.keys[idx]);
+            }
+            emulator.set_i_reg(start.i);
+        }
+
+        for tick in 0..MAX_TICKS {
+            let pc: u16 = emulators[0].pc();
+            let result = emulators[0].tick();
+            prop_assert_eq!(emulators[1].tick(), result, "tick {} at {:#06X}", tick, pc);
+            prop_assert!(emulators[0].save_state() == emulators[1].save_state(), "state differs, tick {} at {:#06X}", tick, pc);
+            if result.is_err() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn recompiler_matches_the_interpreter(
+        quirks in quirks(),
+        rom in program(),
+        start in start(),
+        ticks_per_frame in 1..40usize,
+    ) {
+        let mut emulators: Vec<Emulator> = (0..2).map(|_| Emulator::with_seed(quirks, start.seed)).collect();
+        emulators[1].enable_recompiler();
+        for emulator in emulators.iter_mut() {
+            emulator.load(&rom).unwrap();
+            for idx in 0..16 {
+                emulator.set_v_reg(idx, start.v[idx]);
+                emulator.keypress(idx, start.keys[idx]);
+            }
+            emulator.set_i_reg(start.i);
+        }
+
+        for frame in 0..MAX_TICKS / ticks_per_frame + 1 {
+            let pc: u16 = emulators[0].pc();
+            let result = emulators[0].run_frame(ticks_per_frame);
+            prop_assert_eq!(emulators[1].run_frame(ticks_per_frame), result, "frame {} from {:#06X}", frame, pc);
+            prop_assert!(emulators[0].save_state() == emulators[1].save_state(), "state differs, frame {} from {:#06X}", frame, pc);
+            if result.is_err() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn tick_never_panics(
+        quirks in quirks(),
+        rom in prop::collection::vec(any::<u8>(), 0..512),
+        start in start(),
+        pc in any::<u16>(),
+        sp in 0..=16u16,
+    ) {
+        let mut emulator = Emulator::with_seed(quirks, start.seed);
+        emulator.load(&rom).unwrap();
+        for idx in 0..16 {
+            emulator.set_v_reg(idx, start.v[idx]);
+            emulator.keypress(idx, start.keys[idx]);
+        }
+        emulator.set_i_reg(start.i);
+        emulator.set_sp(sp);
+        // Mostly run the ROM, sometimes start anywhere in memory
+        if pc % 4 == 0 {
+            emulator.set_pc(pc);
+        }
+
+        for _ in 0..MAX_TICKS {
+            if emulator.tick().is_err() {
+                break;
+            }
+        }
+        emulator.tick_timers();
+    }
+}
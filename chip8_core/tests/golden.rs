@@ -0,0 +1,143 @@
+//! Runs the test ROMs in tests/roms for a fixed number of frames, and
+//! compares the screen with the ASCII art in tests/golden.
+//!
+//! Those ROMs are written for this repo, and what they should show is
+//! spelled out in the comment at the top of each: ticks, crosses and
+//! digits whose expected values come from the CHIP-8 specification, not
+//! from running the emulator. The goldens only record that picture.
+//!
+//! The well-known test ROMs aren't vendored, as their licences haven't
+//! been checked for redistribution, so there are no goldens for them.
+//!
+//! After an intended change to a ROM or to the emulator, the goldens
+//! are rewritten by running the tests with CHIP8_UPDATE_GOLDEN=1.
+
+use chip8_core::asm::assemble_file;
+use chip8_core::dump;
+use chip8_core::emulator::{Emulator, NUM_KEYS};
+use chip8_core::quirks::Quirks;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TICKS_PER_FRAME: usize = 20;
+
+/// A key held down from frame until frame + frames
+struct Press {
+    frame: usize,
+    key: usize,
+    frames: usize,
+}
+
+struct Case<'a> {
+    /// The golden's name, tests/golden/{name}.txt
+    name: &'a str,
+    /// The source's name, tests/roms/{rom}.asm
+    rom: &'a str,
+    quirks: Quirks,
+    frames: usize,
+    presses: &'a [Press],
+}
+
+fn run(case: Case) {
+    let root: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let rom: Vec<u8> = assemble_file(&root.join("roms").join(format!("{}.asm", case.rom)))
+        .unwrap_or_else(|err| panic!("{}", err));
+
+    let mut emulator = Emulator::with_seed(case.quirks, 0);
+    emulator.load(&rom).unwrap();
+    for frame in 0..case.frames {
+        for key in 0..NUM_KEYS {
+            let held: bool = case
+                .presses
+                .iter()
+                .any(|press| press.key == key && (press.frame..press.frame + press.frames).contains(&frame));
+            emulator.keypress(key, held);
+        }
+        emulator
+            .run_frame(TICKS_PER_FRAME)
+            .unwrap_or_else(|err| panic!("{} failed on frame {}: {}", case.name, frame, err));
+    }
+
+    compare(case.name, &root.join("golden").join(format!("{}.txt", case.name)), &emulator);
+}
+
+fn compare(name: &str, golden: &Path, emulator: &Emulator) {
+    let actual: String = dump::ascii(emulator.get_display());
+    if env::var_os("CHIP8_UPDATE_GOLDEN").is_some() {
+        fs::write(golden, &actual).unwrap();
+        return;
+    }
+
+    let expected: String = fs::read_to_string(golden).unwrap_or_else(|err| {
+        panic!(
+            "Couldn't read {}: {}\nIf this screen is right, CHIP8_UPDATE_GOLDEN=1 records it:\n{}",
+            golden.display(),
+            err,
+            actual
+        )
+    });
+    if expected != actual {
+        panic!("{} doesn't match {}\n{}", name, golden.display(), diff(&expected, &actual));
+    }
+}
+
+/// Both screens side by side, with the rows that differ marked by a >
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let width: usize = expected.iter().chain(&actual).map(|line| line.len()).max().unwrap_or(0);
+
+    let mut pixels: usize = 0;
+    let mut text: String = format!("    {:<width$}   actual\n", "expected", width = width);
+    for row in 0..expected.len().max(actual.len()) {
+        let left: &str = expected.get(row).copied().unwrap_or("");
+        let right: &str = actual.get(row).copied().unwrap_or("");
+        let differing: usize = left
+            .chars()
+            .zip(right.chars())
+            .filter(|(a, b)| a != b)
+            .count()
+            + left.len().abs_diff(right.len());
+        pixels += differing;
+
+        let marker: char = if differing > 0 { '>' } else { ' ' };
+        text += &format!("{}{:>2} {:<width$} | {}\n", marker, row, left, right, width = width);
+    }
+
+    format!("{} pixels differ\n{}", pixels, text)
+}
+
+#[test]
+fn logo() {
+    run(Case { name: "logo", rom: "logo", quirks: Quirks::default(), frames: 10, presses: &[] });
+}
+
+#[test]
+fn opcodes() {
+    run(Case { name: "opcodes", rom: "opcodes", quirks: Quirks::default(), frames: 60, presses: &[] });
+}
+
+#[test]
+fn flags() {
+    run(Case { name: "flags", rom: "flags", quirks: Quirks::default(), frames: 60, presses: &[] });
+}
+
+#[test]
+fn quirks_cosmac_vip() {
+    run(Case { name: "quirks_cosmac_vip", rom: "quirks", quirks: Quirks::cosmac_vip(), frames: 30, presses: &[] });
+}
+
+#[test]
+fn quirks_super_chip() {
+    run(Case { name: "quirks_super_chip", rom: "quirks", quirks: Quirks::super_chip(), frames: 30, presses: &[] });
+}
+
+#[test]
+fn keypad() {
+    let presses: [Press; 2] = [
+        Press { frame: 5, key: 0xA, frames: 3 },
+        Press { frame: 20, key: 0x3, frames: 3 },
+    ];
+    run(Case { name: "keypad", rom: "keypad", quirks: Quirks::default(), frames: 40, presses: &presses });
+}
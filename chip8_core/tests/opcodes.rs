@@ -0,0 +1,181 @@
+//! Checks every CHIP-8 instruction's effect on the registers, the
+//! stack, RAM and the screen, one table entry per case.
+//!
+//! Everything runs with the default quirks, those of the COSMAC VIP;
+//! the other behaviors are covered by the emulator's own tests.
+
+use chip8_core::emulator::Emulator;
+use chip8_core::error::EmulatorError;
+
+/// A piece of the machine state, to set up before a case or check after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    V(usize, u8),
+    I(u16),
+    Pc(u16),
+    Sp(u16),
+    Dt(u8),
+    St(u8),
+    Ram(usize, u8),
+    Key(usize, bool),
+    /// Only checked: the return address in a stack slot
+    Stack(usize, u16),
+    /// Only checked: the pixel value at (x, y)
+    Pixel(usize, usize, u8),
+}
+
+use State::*;
+
+struct Case {
+    name: &'static str,
+    /// Loaded at 0x200
+    ops: &'static [u16],
+    /// How many instructions to run, the last one may fail
+    ticks: usize,
+    before: &'static [State],
+    after: &'static [State],
+    error: Option<EmulatorError>,
+}
+
+const CASES: &[Case] = &[
+    // 0NNN
+    Case { name: "00E0 clears the screen", ops: &[0xD001, 0x00E0], ticks: 2, before: &[], after: &[Pixel(0, 0, 0), Pixel(3, 0, 0)], error: None },
+    Case { name: "2NNN pushes the return address", ops: &[0x2206], ticks: 1, before: &[], after: &[Pc(0x206), Sp(1), Stack(0, 0x202)], error: None },
+    Case { name: "00EE pops the return address", ops: &[0x2204, 0x0000, 0x00EE], ticks: 2, before: &[], after: &[Pc(0x202), Sp(0)], error: None },
+    Case { name: "00EE with an empty stack", ops: &[0x00EE], ticks: 1, before: &[], after: &[Pc(0x200), Sp(0)], error: Some(EmulatorError::StackUnderflow) },
+    Case { name: "2NNN with a full stack", ops: &[0x2200], ticks: 1, before: &[Sp(16)], after: &[Pc(0x200), Sp(16)], error: Some(EmulatorError::StackOverflow) },
+    Case { name: "1NNN jumps", ops: &[0x1345], ticks: 1, before: &[], after: &[Pc(0x345)], error: None },
+
+    // Skips
+    Case { name: "3XNN skips if equal", ops: &[0x3342], ticks: 1, before: &[V(3, 0x42)], after: &[Pc(0x204)], error: None },
+    Case { name: "3XNN doesn't skip if different", ops: &[0x3342], ticks: 1, before: &[V(3, 0x41)], after: &[Pc(0x202)], error: None },
+    Case { name: "4XNN skips if different", ops: &[0x4342], ticks: 1, before: &[V(3, 0x41)], after: &[Pc(0x204)], error: None },
+    Case { name: "4XNN doesn't skip if equal", ops: &[0x4342], ticks: 1, before: &[V(3, 0x42)], after: &[Pc(0x202)], error: None },
+    Case { name: "5XY0 skips if equal", ops: &[0x5120], ticks: 1, before: &[V(1, 7), V(2, 7)], after: &[Pc(0x204)], error: None },
+    Case { name: "5XY0 doesn't skip if different", ops: &[0x5120], ticks: 1, before: &[V(1, 7), V(2, 8)], after: &[Pc(0x202)], error: None },
+    Case { name: "9XY0 skips if different", ops: &[0x9120], ticks: 1, before: &[V(1, 7), V(2, 8)], after: &[Pc(0x204)], error: None },
+    Case { name: "9XY0 doesn't skip if equal", ops: &[0x9120], ticks: 1, before: &[V(1, 7), V(2, 7)], after: &[Pc(0x202)], error: None },
+
+    // Registers
+    Case { name: "6XNN loads", ops: &[0x6A42], ticks: 1, before: &[], after: &[V(0xA, 0x42)], error: None },
+    Case { name: "7XNN wraps without touching VF", ops: &[0x7A02], ticks: 1, before: &[V(0xA, 0xFF), V(0xF, 7)], after: &[V(0xA, 1), V(0xF, 7)], error: None },
+    Case { name: "8XY0 copies", ops: &[0x8120], ticks: 1, before: &[V(2, 0x42)], after: &[V(1, 0x42), V(2, 0x42)], error: None },
+    Case { name: "8XY1 ors and resets VF", ops: &[0x8121], ticks: 1, before: &[V(1, 0x3C), V(2, 0x0F), V(0xF, 1)], after: &[V(1, 0x3F), V(0xF, 0)], error: None },
+    Case { name: "8XY2 ands and resets VF", ops: &[0x8122], ticks: 1, before: &[V(1, 0x3C), V(2, 0x0F), V(0xF, 1)], after: &[V(1, 0x0C), V(0xF, 0)], error: None },
+    Case { name: "8XY3 xors and resets VF", ops: &[0x8123], ticks: 1, before: &[V(1, 0x3C), V(2, 0x0F), V(0xF, 1)], after: &[V(1, 0x33), V(0xF, 0)], error: None },
+
+    // Arithmetic flags
+    Case { name: "8XY4 without carry", ops: &[0x8124], ticks: 1, before: &[V(1, 0xFE), V(2, 1), V(0xF, 1)], after: &[V(1, 0xFF), V(0xF, 0)], error: None },
+    Case { name: "8XY4 with carry", ops: &[0x8124], ticks: 1, before: &[V(1, 0xFF), V(2, 2)], after: &[V(1, 1), V(0xF, 1)], error: None },
+    Case { name: "8XY4 into VF keeps the carry", ops: &[0x8F24], ticks: 1, before: &[V(0xF, 0xFF), V(2, 2)], after: &[V(0xF, 1)], error: None },
+    Case { name: "8XY5 without borrow", ops: &[0x8125], ticks: 1, before: &[V(1, 3), V(2, 1)], after: &[V(1, 2), V(0xF, 1)], error: None },
+    Case { name: "8XY5 of equal values", ops: &[0x8125], ticks: 1, before: &[V(1, 3), V(2, 3)], after: &[V(1, 0), V(0xF, 1)], error: None },
+    Case { name: "8XY5 with borrow", ops: &[0x8125], ticks: 1, before: &[V(1, 1), V(2, 3), V(0xF, 1)], after: &[V(1, 0xFE), V(0xF, 0)], error: None },
+    Case { name: "8XY7 without borrow", ops: &[0x8127], ticks: 1, before: &[V(1, 1), V(2, 3)], after: &[V(1, 2), V(0xF, 1)], error: None },
+    Case { name: "8XY7 with borrow", ops: &[0x8127], ticks: 1, before: &[V(1, 3), V(2, 1), V(0xF, 1)], after: &[V(1, 0xFE), V(0xF, 0)], error: None },
+    Case { name: "8XY6 shifts VY right", ops: &[0x8126], ticks: 1, before: &[V(1, 0xFF), V(2, 0x81)], after: &[V(1, 0x40), V(2, 0x81), V(0xF, 1)], error: None },
+    Case { name: "8XY6 without a bit out", ops: &[0x8126], ticks: 1, before: &[V(2, 0x80), V(0xF, 1)], after: &[V(1, 0x40), V(0xF, 0)], error: None },
+    Case { name: "8XYE shifts VY left", ops: &[0x812E], ticks: 1, before: &[V(1, 0xFF), V(2, 0x81)], after: &[V(1, 0x02), V(2, 0x81), V(0xF, 1)], error: None },
+    Case { name: "8XYE without a bit out", ops: &[0x812E], ticks: 1, before: &[V(2, 0x01), V(0xF, 1)], after: &[V(1, 0x02), V(0xF, 0)], error: None },
+
+    // I and jumps
+    Case { name: "ANNN loads I", ops: &[0xA345], ticks: 1, before: &[], after: &[I(0x345)], error: None },
+    Case { name: "BNNN jumps to NNN + V0", ops: &[0xB300], ticks: 1, before: &[V(0, 0x10), V(3, 0x20)], after: &[Pc(0x310)], error: None },
+    Case { name: "CXNN masks the random byte", ops: &[0xC100], ticks: 1, before: &[V(1, 0x42)], after: &[V(1, 0)], error: None },
+
+    // Drawing
+    Case { name: "DXYN draws the sprite at I", ops: &[0xD012], ticks: 1, before: &[I(0), V(0, 2), V(1, 3)], after: &[Pixel(2, 3, 1), Pixel(5, 3, 1), Pixel(6, 3, 0), Pixel(2, 4, 1), Pixel(3, 4, 0), V(0xF, 0)], error: None },
+    Case { name: "DXYN erases and reports collisions", ops: &[0xD012, 0xD012], ticks: 2, before: &[I(0)], after: &[Pixel(0, 0, 0), Pixel(0, 1, 0), V(0xF, 1)], error: None },
+
+    // Keys
+    Case { name: "EX9E skips if pressed", ops: &[0xE59E], ticks: 1, before: &[V(5, 0xA), Key(0xA, true)], after: &[Pc(0x204)], error: None },
+    Case { name: "EX9E doesn't skip if released", ops: &[0xE59E], ticks: 1, before: &[V(5, 0xA)], after: &[Pc(0x202)], error: None },
+    Case { name: "EXA1 skips if released", ops: &[0xE5A1], ticks: 1, before: &[V(5, 0xA)], after: &[Pc(0x204)], error: None },
+    Case { name: "EXA1 doesn't skip if pressed", ops: &[0xE5A1], ticks: 1, before: &[V(5, 0xA), Key(0xA, true)], after: &[Pc(0x202)], error: None },
+    Case { name: "FX0A waits for a key", ops: &[0xF50A], ticks: 3, before: &[V(5, 0x42)], after: &[Pc(0x200), V(5, 0x42)], error: None },
+    Case { name: "FX0A stores the pressed key", ops: &[0xF50A], ticks: 1, before: &[Key(0xC, true)], after: &[Pc(0x202), V(5, 0xC)], error: None },
+
+    // Timers
+    Case { name: "FX07 reads the delay timer", ops: &[0xF507], ticks: 1, before: &[Dt(0x42)], after: &[V(5, 0x42)], error: None },
+    Case { name: "FX15 sets the delay timer", ops: &[0xF515], ticks: 1, before: &[V(5, 0x42)], after: &[Dt(0x42)], error: None },
+    Case { name: "FX18 sets the sound timer", ops: &[0xF518], ticks: 1, before: &[V(5, 0x42)], after: &[St(0x42)], error: None },
+
+    // Memory
+    Case { name: "FX1E adds to I", ops: &[0xF51E], ticks: 1, before: &[I(0x300), V(5, 0x42)], after: &[I(0x342)], error: None },
+    Case { name: "FX1E past 0xFFF leaves VF alone", ops: &[0xF51E], ticks: 1, before: &[I(0xFFF), V(5, 2), V(0xF, 7)], after: &[I(0x1001), V(0xF, 7)], error: None },
+    Case { name: "FX29 points I to the glyph", ops: &[0xF529], ticks: 1, before: &[V(5, 0xA)], after: &[I(50)], error: None },
+    Case { name: "FX29 only uses the lowest digit", ops: &[0xF529], ticks: 1, before: &[V(5, 0x1A)], after: &[I(50)], error: None },
+    Case { name: "FX33 of 255", ops: &[0xF533], ticks: 1, before: &[I(0x300), V(5, 255)], after: &[Ram(0x300, 2), Ram(0x301, 5), Ram(0x302, 5), I(0x300)], error: None },
+    Case { name: "FX33 of 109", ops: &[0xF533], ticks: 1, before: &[I(0x300), V(5, 109)], after: &[Ram(0x300, 1), Ram(0x301, 0), Ram(0x302, 9)], error: None },
+    Case { name: "FX33 of 0", ops: &[0xF533], ticks: 1, before: &[I(0x300), V(5, 0), Ram(0x302, 7)], after: &[Ram(0x300, 0), Ram(0x301, 0), Ram(0x302, 0)], error: None },
+    Case { name: "FX55 stores V0 to VX", ops: &[0xF255], ticks: 1, before: &[I(0x300), V(0, 1), V(1, 2), V(2, 3), V(3, 4)], after: &[Ram(0x300, 1), Ram(0x301, 2), Ram(0x302, 3), Ram(0x303, 0), I(0x303)], error: None },
+    Case { name: "FX65 loads V0 to VX", ops: &[0xF265], ticks: 1, before: &[I(0x300), Ram(0x300, 1), Ram(0x301, 2), Ram(0x302, 3), Ram(0x303, 4)], after: &[V(0, 1), V(1, 2), V(2, 3), V(3, 0), I(0x303)], error: None },
+    Case { name: "FX55 past the end of RAM", ops: &[0xF155], ticks: 1, before: &[I(0xFFFF)], after: &[Pc(0x200)], error: Some(EmulatorError::MemoryOutOfBounds { addr: 0x10000 }) },
+];
+
+fn set(emulator: &mut Emulator, state: State) {
+    match state {
+        V(idx, value) => emulator.set_v_reg(idx, value),
+        I(value) => emulator.set_i_reg(value),
+        Pc(pc) => emulator.set_pc(pc),
+        Sp(sp) => emulator.set_sp(sp),
+        Dt(value) => emulator.set_dt(value),
+        St(value) => emulator.set_st(value),
+        Ram(addr, value) => emulator.poke(addr, value).unwrap(),
+        Key(idx, pressed) => emulator.keypress(idx, pressed),
+        Stack(..) | Pixel(..) => panic!("{:?} can only be checked", state),
+    }
+}
+
+/// The actual value of the same piece of state, to compare with the expected one
+fn get(emulator: &Emulator, state: State) -> State {
+    match state {
+        V(idx, _) => V(idx, emulator.v_reg()[idx]),
+        I(_) => I(emulator.i_reg()),
+        Pc(_) => Pc(emulator.pc()),
+        Sp(_) => Sp(emulator.sp()),
+        Dt(_) => Dt(emulator.dt()),
+        St(_) => St(emulator.st()),
+        Ram(addr, _) => Ram(addr, emulator.ram()[addr]),
+        Key(idx, _) => Key(idx, emulator.keys()[idx]),
+        Stack(idx, _) => Stack(idx, emulator.stack()[idx]),
+        Pixel(x, y, _) => {
+            let screen = emulator.get_display();
+            Pixel(x, y, screen.pixels()[x + screen.width() * y])
+        }
+    }
+}
+
+#[test]
+fn every_opcode_conforms() {
+    let mut failures: Vec<String> = Vec::new();
+
+    for case in CASES {
+        let rom: Vec<u8> = case.ops.iter().flat_map(|op| op.to_be_bytes()).collect();
+        let mut emulator = Emulator::with_seed(Default::default(), 0);
+        emulator.load(&rom).unwrap();
+        for &state in case.before {
+            set(&mut emulator, state);
+        }
+
+        let mut result: Result<(), EmulatorError> = Ok(());
+        for _ in 0..case.ticks {
+            result = emulator.tick();
+            if result.is_err() {
+                break;
+            }
+        }
+
+        if result != case.error.map_or(Ok(()), Err) {
+            failures.push(format!("{}: expected {:?}, got {:?}", case.name, case.error, result));
+        }
+        for &expected in case.after {
+            let actual: State = get(&emulator, expected);
+            if actual != expected {
+                failures.push(format!("{}: expected {:?}, got {:?}", case.name, expected, actual));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{} checks failed:\n{}", failures.len(), failures.join("\n"));
+}
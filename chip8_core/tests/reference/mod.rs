@@ -0,0 +1,226 @@
+//! A plain CHIP-8 interpreter, written straight from the specification
+//! to check Emulator against. It leaves out the SUPER-CHIP and XO-CHIP
+//! extensions, and DXY0: step() reports those as unsupported.
+
+use chip8_core::error::EmulatorError;
+use chip8_core::quirks::{IndexIncrement, Quirks};
+use chip8_core::random::Xorshift;
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+const STACK_SIZE: usize = 16;
+
+/// What a step did
+#[derive(Debug, PartialEq, Eq)]
+pub enum Step {
+    Done,
+    Failed(EmulatorError),
+    /// The opcode isn't plain CHIP-8, nothing was changed
+    Unsupported(u16),
+}
+
+pub struct Reference {
+    pub ram: Vec<u8>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub dt: u8,
+    pub st: u8,
+    pub keys: [bool; 16],
+    pub screen: [bool; WIDTH * HEIGHT],
+    quirks: Quirks,
+    rng: Xorshift,
+}
+
+impl Reference {
+
+    /// Starts from a copy of Emulator's memory, ROM and fonts loaded,
+    /// so that both agree on where things are
+    pub fn new(quirks: Quirks, seed: u64, ram: &[u8]) -> Self {
+        Self {
+            ram: ram.to_vec(),
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            dt: 0,
+            st: 0,
+            keys: [false; 16],
+            screen: [false; WIDTH * HEIGHT],
+            quirks,
+            rng: Xorshift::new(seed),
+        }
+    }
+
+    /// Runs one instruction. On failure the pc is left on it.
+    pub fn step(&mut self) -> Step {
+        let pc: u16 = self.pc;
+        match self.execute() {
+            Ok(step) => {
+                if step != Step::Done {
+                    self.pc = pc;
+                }
+                step
+            }
+            Err(err) => {
+                self.pc = pc;
+                Step::Failed(err)
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Result<Step, EmulatorError> {
+        let op: u16 = u16::from_be_bytes([self.read(self.pc as usize)?, self.read(self.pc as usize + 1)?]);
+        self.pc = self.pc.wrapping_add(2);
+
+        let x: usize = ((op >> 8) & 0xF) as usize;
+        let y: usize = ((op >> 4) & 0xF) as usize;
+        let n: u8 = (op & 0xF) as u8;
+        let nn: u8 = (op & 0xFF) as u8;
+        let nnn: u16 = op & 0xFFF;
+
+        match (op >> 12, n) {
+            (0x0, _) if op == 0x00E0 => self.screen = [false; WIDTH * HEIGHT],
+            (0x0, _) if op == 0x00EE => self.pc = self.stack.pop().ok_or(EmulatorError::StackUnderflow)?,
+            (0x1, _) => self.pc = nnn,
+            (0x2, _) => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(EmulatorError::StackOverflow);
+                }
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            }
+            (0x3, _) => self.skip_if(self.v[x] == nn),
+            (0x4, _) => self.skip_if(self.v[x] != nn),
+            (0x5, 0) => self.skip_if(self.v[x] == self.v[y]),
+            (0x6, _) => self.v[x] = nn,
+            (0x7, _) => self.v[x] = self.v[x].wrapping_add(nn),
+            (0x8, 0x0) => self.v[x] = self.v[y],
+            (0x8, 0x1..=0x3) => {
+                self.v[x] = match n {
+                    0x1 => self.v[x] | self.v[y],
+                    0x2 => self.v[x] & self.v[y],
+                    _ => self.v[x] ^ self.v[y],
+                };
+                if self.quirks.logic_resets_vf {
+                    self.v[0xF] = 0;
+                }
+            }
+            // The flag is written last, so it wins when X is F
+            (0x8, 0x4) => {
+                let (sum, carry) = self.v[x].overflowing_add(self.v[y]);
+                self.set_with_flag(x, sum, carry);
+            }
+            (0x8, 0x5) => {
+                let (difference, borrow) = self.v[x].overflowing_sub(self.v[y]);
+                self.set_with_flag(x, difference, !borrow);
+            }
+            (0x8, 0x7) => {
+                let (difference, borrow) = self.v[y].overflowing_sub(self.v[x]);
+                self.set_with_flag(x, difference, !borrow);
+            }
+            (0x8, 0x6) => {
+                let source: u8 = if self.quirks.shift_uses_vy { self.v[y] } else { self.v[x] };
+                self.set_with_flag(x, source >> 1, source & 1 == 1);
+            }
+            (0x8, 0xE) => {
+                let source: u8 = if self.quirks.shift_uses_vy { self.v[y] } else { self.v[x] };
+                self.set_with_flag(x, source << 1, source & 0x80 != 0);
+            }
+            (0x9, 0) => self.skip_if(self.v[x] != self.v[y]),
+            (0xA, _) => self.i = nnn,
+            (0xB, _) => {
+                let offset: u8 = if self.quirks.jump_uses_vx { self.v[x] } else { self.v[0] };
+                self.pc = nnn + offset as u16;
+            }
+            (0xC, _) => self.v[x] = self.rng.next_byte() & nn,
+            (0xD, 1..) => self.draw(self.v[x] as usize, self.v[y] as usize, n as usize)?,
+            (0xE, _) if nn == 0x9E => self.skip_if(self.keys[(self.v[x] & 0xF) as usize]),
+            (0xE, _) if nn == 0xA1 => self.skip_if(!self.keys[(self.v[x] & 0xF) as usize]),
+            (0xF, _) if nn == 0x07 => self.v[x] = self.dt,
+            (0xF, _) if nn == 0x0A => match self.keys.iter().position(|&pressed| pressed) {
+                Some(key) => self.v[x] = key as u8,
+                None => self.pc = self.pc.wrapping_sub(2),
+            },
+            (0xF, _) if nn == 0x15 => self.dt = self.v[x],
+            (0xF, _) if nn == 0x18 => self.st = self.v[x],
+            (0xF, _) if nn == 0x1E => self.i = self.i.wrapping_add(self.v[x] as u16),
+            (0xF, _) if nn == 0x29 => self.i = (self.v[x] & 0xF) as u16 * 5,
+            (0xF, _) if nn == 0x33 => {
+                let i: usize = self.i as usize;
+                self.write(i, self.v[x] / 100)?;
+                self.write(i + 1, self.v[x] / 10 % 10)?;
+                self.write(i + 2, self.v[x] % 10)?;
+            }
+            (0xF, _) if nn == 0x55 || nn == 0x65 => {
+                for idx in 0..=x {
+                    let addr: usize = self.i as usize + idx;
+                    if nn == 0x55 {
+                        self.write(addr, self.v[idx])?;
+                    } else {
+                        self.v[idx] = self.read(addr)?;
+                    }
+                }
+                self.i = match self.quirks.load_store_increment {
+                    IndexIncrement::Unchanged => self.i,
+                    IndexIncrement::X => self.i.wrapping_add(x as u16),
+                    IndexIncrement::XPlusOne => self.i.wrapping_add(x as u16 + 1),
+                };
+            }
+            _ => return Ok(Step::Unsupported(op)),
+        }
+
+        Ok(Step::Done)
+    }
+
+    fn read(&self, addr: usize) -> Result<u8, EmulatorError> {
+        self.ram.get(addr).copied().ok_or(EmulatorError::MemoryOutOfBounds { addr })
+    }
+
+    fn write(&mut self, addr: usize, value: u8) -> Result<(), EmulatorError> {
+        *self.ram.get_mut(addr).ok_or(EmulatorError::MemoryOutOfBounds { addr })? = value;
+        Ok(())
+    }
+
+    fn set_with_flag(&mut self, x: usize, value: u8, flag: bool) {
+        self.v[x] = value;
+        self.v[0xF] = flag as u8;
+    }
+
+    /// Skips the next instruction, all 4 bytes of it for an XO-CHIP long load,
+    /// as Emulator also runs XO-CHIP programs
+    fn skip_if(&mut self, condition: bool) {
+        if !condition {
+            return;
+        }
+        let pc: usize = self.pc as usize;
+        let is_long_load: bool = self.ram.get(pc) == Some(&0xF0) && self.ram.get(pc + 1) == Some(&0x00);
+        self.pc = self.pc.wrapping_add(if is_long_load { 4 } else { 2 });
+    }
+
+    fn draw(&mut self, x: usize, y: usize, rows: usize) -> Result<(), EmulatorError> {
+        let mut collision: bool = false;
+        for row in 0..rows {
+            let bits: u8 = self.read(self.i as usize + row)?;
+            for col in 0..8 {
+                if bits & (0x80 >> col) == 0 {
+                    continue;
+                }
+                let (mut px, mut py) = (x % WIDTH + col, y % HEIGHT + row);
+                if px >= WIDTH || py >= HEIGHT {
+                    if self.quirks.clip_sprites {
+                        continue;
+                    }
+                    px %= WIDTH;
+                    py %= HEIGHT;
+                }
+                let pixel: &mut bool = &mut self.screen[px + py * WIDTH];
+                collision |= *pixel;
+                *pixel = !*pixel;
+            }
+        }
+        self.v[0xF] = collision as u8;
+        Ok(())
+    }
+}
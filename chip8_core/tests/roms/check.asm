@@ -0,0 +1,27 @@
+; Shared by the test ROMs: draws a result cell, left to right in rows of 10,
+; starting from (V8, V9). Keeps clear of V0-V3 and V8-V9 otherwise.
+
+; A tick if V0 == V1, a cross otherwise
+check:
+    LD I, cross
+    SE V0, V1
+    JP check_draw
+    LD I, tick
+check_draw:
+    DRW V8, V9, 4
+    ADD V8, 6
+    SE V8, 62
+    RET
+    LD V8, 2
+    ADD V9, 6
+    RET
+
+; The hex digit in V3, 5 pixels apart
+digit:
+    LD F, V3
+    DRW V8, V9, 5
+    ADD V8, 5
+    RET
+
+tick: db 0b00001000, 0b00010000, 0b10100000, 0b01000000
+cross: db 0b10010000, 0b01100000, 0b01100000, 0b10010000
@@ -0,0 +1,146 @@
+; A tick or a cross per check of VF, in this order:
+;  8XY4 carry, no carry, VF as VX
+;  8XY5 borrow, no borrow, equal, VF as VX
+;  8XY7 borrow, no borrow, VF as VX
+;  8XY6 bit out, no bit out, VF as VX
+;  8XYE bit out, no bit out, VF as VX
+;  DXYN collision, no collision
+; "VF as VX" checks that the flag wins over the result.
+    CLS
+    LD V8, 2
+    LD V9, 2
+
+    ; 8XY4
+    LD V0, 0xFF
+    LD V2, 2
+    ADD V0, V2
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    LD V0, 0xFE
+    LD V2, 1
+    ADD V0, V2
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    LD VF, 0xFF
+    LD V2, 2
+    ADD VF, V2
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    ; 8XY5
+    LD V0, 1
+    LD V2, 2
+    SUB V0, V2
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    LD V0, 2
+    LD V2, 1
+    SUB V0, V2
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    LD V0, 2
+    LD V2, 2
+    SUB V0, V2
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    LD VF, 1
+    LD V2, 2
+    SUB VF, V2
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    ; 8XY7
+    LD V0, 2
+    LD V2, 1
+    SUBN V0, V2
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    LD V0, 1
+    LD V2, 2
+    SUBN V0, V2
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    LD VF, 2
+    LD V2, 1
+    SUBN VF, V2
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    ; 8XY6, shifting V0 itself so both quirks agree
+    LD V0, 0x03
+    SHR V0, V0
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    LD V0, 0x02
+    SHR V0, V0
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    LD VF, 0x02
+    SHR VF, VF
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    ; 8XYE
+    LD V0, 0x81
+    SHL V0, V0
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    LD V0, 0x41
+    SHL V0, V0
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    LD VF, 0x41
+    SHL VF, VF
+    LD V0, VF
+    LD V1, 0
+    CALL check
+
+    ; DXYN, on the bottom row, erasing the sprite once done
+    LD V2, 0
+    LD V3, 31
+    LD I, dot
+    DRW V2, V3, 1
+    DRW V2, V3, 1
+    LD V0, VF
+    LD V1, 1
+    CALL check
+
+    LD I, dot
+    DRW V2, V3, 1
+    LD V0, VF
+    DRW V2, V3, 1
+    LD V1, 0
+    CALL check
+
+end:
+    JP end
+
+dot: db 0x80
+
+    include "check.asm"
@@ -0,0 +1,34 @@
+; Waits for a key twice. Each time it draws the key's digit, then a tick
+; or a cross for EX9E seeing it held, then for EXA1 seeing it released.
+    CLS
+    LD V8, 2
+    LD V9, 2
+    CALL key_test
+    LD V8, 2
+    LD V9, 10
+    CALL key_test
+end:
+    JP end
+
+key_test:
+    LD V3, K
+    CALL digit
+    ADD V8, 1
+
+    ; EX9E, right after FX0A saw the key
+    LD V0, 0
+    SKP V3
+    LD V0, 1
+    LD V1, 0
+    CALL check
+
+    ; EXA1, waiting for the release
+release_wait:
+    SKNP V3
+    JP release_wait
+    LD V0, 0
+    LD V1, 0
+    CALL check
+    RET
+
+    include "check.asm"
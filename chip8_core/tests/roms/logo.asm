@@ -0,0 +1,29 @@
+; Clears the screen and draws a 32x10 "RUST" logo in the middle of it,
+; as 8 pixel wide columns of sprites
+    CLS
+    LD V0, 16
+    LD V1, 11
+    LD I, logo
+    DRW V0, V1, 10
+    ADD V0, 8
+    LD V2, 10
+    ADD I, V2
+    DRW V0, V1, 10
+    ADD V0, 8
+    ADD I, V2
+    DRW V0, V1, 10
+    ADD V0, 8
+    ADD I, V2
+    DRW V0, V1, 10
+end:
+    JP end
+
+logo:
+    db 0b11111100, 0b11111110, 0b11000110, 0b11000110, 0b11111100
+    db 0b11111000, 0b11011100, 0b11001110, 0b11000110, 0b11000110
+    db 0b11000110, 0b11000110, 0b11000110, 0b11000110, 0b11000110
+    db 0b11000110, 0b11000110, 0b11000110, 0b11111110, 0b01111100
+    db 0b01111110, 0b11111110, 0b11000000, 0b11000000, 0b11111100
+    db 0b01111110, 0b00000110, 0b00000110, 0b11111110, 0b11111100
+    db 0b11111110, 0b11111110, 0b00111000, 0b00111000, 0b00111000
+    db 0b00111000, 0b00111000, 0b00111000, 0b00111000, 0b00111000
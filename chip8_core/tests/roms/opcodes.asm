@@ -0,0 +1,219 @@
+; A tick or a cross per check, in this order:
+;  1NNN  2NNN/00EE  3XNN  4XNN  5XY0  6XNN  7XNN  8XY0  8XY1  8XY2
+;  8XY3  8XY4  8XY5  8XY7  8XY6  8XYE  ANNN/FX1E  FX55/FX65  FX29  BNNN
+;  CXNN  FX15/FX07  9XY0  FX33
+; Quirk dependent behavior is left to quirks.asm, and flags to flags.asm.
+    CLS
+    LD V8, 2
+    LD V9, 2
+
+    ; 1NNN
+    LD V0, 1
+    JP jp_done
+    LD V0, 0
+jp_done:
+    LD V1, 1
+    CALL check
+
+    ; 2NNN and 00EE
+    LD V0, 0
+    CALL set_v0
+    LD V1, 0x55
+    CALL check
+
+    ; 3XNN: skips if equal, and only then
+    LD V0, 1
+    SE V0, 1
+    LD V0, 0
+    SE V0, 2
+    ADD V0, 2
+    LD V1, 3
+    CALL check
+
+    ; 4XNN
+    LD V0, 1
+    SNE V0, 2
+    LD V0, 0
+    SNE V0, 1
+    ADD V0, 2
+    LD V1, 3
+    CALL check
+
+    ; 5XY0
+    LD V0, 1
+    LD V2, 1
+    SE V0, V2
+    LD V0, 0
+    LD V2, 2
+    SE V0, V2
+    ADD V0, 2
+    LD V1, 3
+    CALL check
+
+    ; 6XNN
+    LD V0, 0x42
+    LD V1, 0x42
+    CALL check
+
+    ; 7XNN wraps around and leaves VF alone
+    LD VF, 7
+    LD V0, 0xFF
+    ADD V0, 2
+    ADD V0, VF
+    LD V1, 8
+    CALL check
+
+    ; 8XY0
+    LD V2, 0x42
+    LD V0, V2
+    LD V1, 0x42
+    CALL check
+
+    ; 8XY1
+    LD V0, 0x3C
+    LD V2, 0x0F
+    OR V0, V2
+    LD V1, 0x3F
+    CALL check
+
+    ; 8XY2
+    LD V0, 0x3C
+    LD V2, 0x0F
+    AND V0, V2
+    LD V1, 0x0C
+    CALL check
+
+    ; 8XY3
+    LD V0, 0x3C
+    LD V2, 0x0F
+    XOR V0, V2
+    LD V1, 0x33
+    CALL check
+
+    ; 8XY4
+    LD V0, 0x10
+    LD V2, 0x20
+    ADD V0, V2
+    LD V1, 0x30
+    CALL check
+
+    ; 8XY5
+    LD V0, 0x20
+    LD V2, 0x30
+    SUB V0, V2
+    LD V1, 0xF0
+    CALL check
+
+    ; 8XY7
+    LD V0, 0x30
+    LD V2, 0x20
+    SUBN V0, V2
+    LD V1, 0xF0
+    CALL check
+
+    ; 8XY6, shifting V0 itself so both quirks agree
+    LD V0, 0x81
+    SHR V0, V0
+    LD V1, 0x40
+    CALL check
+
+    ; 8XYE
+    LD V0, 0x81
+    SHL V0, V0
+    LD V1, 0x02
+    CALL check
+
+    ; ANNN and FX1E
+    LD I, bytes
+    LD V2, 2
+    ADD I, V2
+    LD V0, [I]
+    LD V1, 0x33
+    CALL check
+
+    ; FX55 and FX65
+    LD I, scratch
+    LD V0, 1
+    LD V1, 2
+    LD V2, 3
+    LD [I], V2
+    LD V0, 0
+    LD V1, 0
+    LD V2, 0
+    LD I, scratch
+    LD V2, [I]
+    ADD V0, V1
+    ADD V0, V2
+    LD V1, 6
+    CALL check
+
+    ; FX29, the top row of the A glyph
+    LD V0, 0xA
+    LD F, V0
+    LD V0, [I]
+    LD V1, 0xF0
+    CALL check
+
+    ; BNNN, with V0 and V2 equal so both quirks agree
+    LD V0, 2
+    LD V2, 2
+    JP V0, jump_table
+jump_table:
+    JP jump_wrong
+    JP jump_right
+jump_wrong:
+    LD V0, 0
+    JP jump_done
+jump_right:
+    LD V0, 1
+jump_done:
+    LD V1, 1
+    CALL check
+
+    ; CXNN masks the random byte
+    RND V0, 0
+    LD V1, 0
+    CALL check
+
+    ; FX15 and FX07: the delay timer counts down to 0
+    LD V0, 5
+    LD DT, V0
+timer_wait:
+    LD V0, DT
+    SE V0, 0
+    JP timer_wait
+    LD V1, 0
+    CALL check
+
+    ; 9XY0: skips if different, and only then
+    LD V0, 1
+    LD V2, 2
+    SNE V0, V2
+    LD V0, 0
+    LD V2, 1
+    SNE V0, V2
+    ADD V0, 2
+    LD V1, 3
+    CALL check
+
+    ; FX33 of 255, added up digit by digit
+    LD V0, 255
+    LD I, scratch
+    LD B, V0
+    LD V2, [I]
+    ADD V0, V1
+    ADD V0, V2
+    LD V1, 12
+    CALL check
+
+end:
+    JP end
+
+set_v0:
+    LD V0, 0x55
+    RET
+
+bytes: db 0x11, 0x22, 0x33
+scratch: db 0, 0, 0
+
+    include "check.asm"
@@ -0,0 +1,76 @@
+; A digit per quirk, in this order:
+;  8XY6 shifts VY (1) or VX (0)
+;  FX55/FX65 leave I untouched (0), add X (1) or X + 1 (2)
+;  DXYN clips (1) or wraps (0) sprites
+;  8XY1 resets VF (1) or not (0)
+;  BNNN jumps to XNN + VX (1) or NNN + V0 (0)
+    CLS
+    LD V8, 2
+    LD V9, 2
+
+    ; 8XY6
+    LD V0, 1
+    LD V2, 0x80
+    SHR V0, V2
+    LD V3, 0
+    SNE V0, 0x40
+    LD V3, 1
+    CALL digit
+
+    ; FX55, then FX65 reads whichever byte I ended on
+    LD I, scratch
+    LD V0, 0
+    LD V1, 1
+    LD [I], V1
+    LD V0, [I]
+    LD V3, V0
+    CALL digit
+
+    ; DXYN, 2 pixels from the right edge of the bottom row, then whatever
+    ; wrapped to the left edge. Drawn twice to leave the screen blank.
+    LD V0, 63
+    LD V1, 0
+    LD V2, 31
+    LD I, two_dots
+    DRW V0, V2, 1
+    LD I, one_dot
+    DRW V1, V2, 1
+    LD V3, 1
+    SE VF, 0
+    LD V3, 0
+    DRW V1, V2, 1
+    LD I, two_dots
+    DRW V0, V2, 1
+    CALL digit
+
+    ; 8XY1
+    LD VF, 5
+    OR V0, V0
+    LD V3, 0
+    SNE VF, 0
+    LD V3, 1
+    CALL digit
+
+    ; BNNN, with the table below 0x300 so X is 2
+    LD V0, 0
+    LD V2, 2
+    JP V0, jump_table
+jump_table:
+    JP jump_v0
+    JP jump_vx
+jump_v0:
+    LD V3, 0
+    JP jump_done
+jump_vx:
+    LD V3, 1
+jump_done:
+    CALL digit
+
+end:
+    JP end
+
+two_dots: db 0xC0
+one_dot: db 0x80
+scratch: db 0xFF, 0xFF, 2
+
+    include "check.asm"
@@ -0,0 +1,83 @@
+use sdl2::audio::AudioCallback;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name.to_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "sine" => Some(Waveform::Sine),
+            "triangle" => Some(Waveform::Triangle),
+            _ => None,
+        }
+    }
+
+    /// Value of the wave at phase (0 to 1), between -1 and 1
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+/// Tone played while the emulator's sound timer is running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeepSettings {
+    pub frequency: f32, // Hz
+    pub volume: f32, // 0 to 1
+    pub waveform: Waveform,
+}
+
+impl Default for BeepSettings {
+    fn default() -> Self {
+        Self {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// SDL audio callback. The main loop turns it on and off through
+/// the device lock, following the emulator's sound timer.
+pub struct Beeper {
+    pub active: bool,
+    settings: BeepSettings,
+    phase_inc: f32, // Phase advanced on every sample
+    phase: f32,
+}
+
+impl Beeper {
+
+    pub fn new(settings: BeepSettings, sample_rate: i32) -> Self {
+        Self {
+            active: false,
+            settings,
+            phase_inc: settings.frequency / sample_rate as f32,
+            phase: 0.0,
+        }
+    }
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.active {
+                self.settings.waveform.sample(self.phase) * self.settings.volume
+            } else {
+                0.0
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
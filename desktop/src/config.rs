@@ -0,0 +1,76 @@
+use crate::audio::{BeepSettings, Waveform};
+use crate::keymap::{Keymap, Layout};
+use sdl2::keyboard::Keycode;
+use std::fs;
+
+/// Settings read from the config file, one `name = value` per line.
+/// Lines starting with # are comments. See chip8.example.cfg.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub keymap: Keymap,
+    pub beep: BeepSettings,
+}
+
+impl Config {
+
+    pub fn load(path: &str) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Config::parse(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        // Remaps are applied once the layout is known, wherever it is in the file
+        let mut remaps: Vec<(usize, Keycode)> = Vec::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line_num: usize = idx + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .ok_or(format!("line {}: expected `name = value`", line_num))?;
+
+            if name == "layout" {
+                let layout = Layout::from_name(value)
+                    .ok_or(format!("line {}: unknown layout `{}`", line_num, value))?;
+                config.keymap = Keymap::new(layout);
+            } else if let Some(hex_key) = name.strip_prefix("key.") {
+                let hex_key = usize::from_str_radix(hex_key, 16)
+                    .ok()
+                    .filter(|&key| key < 16)
+                    .ok_or(format!("line {}: `{}` is not a hex key, use key.0 to key.F", line_num, name))?;
+                let keycode = Keycode::from_name(value)
+                    .ok_or(format!("line {}: unknown key `{}`", line_num, value))?;
+                remaps.push((hex_key, keycode));
+            } else if name == "audio.frequency" {
+                config.beep.frequency = value
+                    .parse()
+                    .ok()
+                    .filter(|&frequency: &f32| frequency > 0.0)
+                    .ok_or(format!("line {}: the frequency must be a positive number of Hz", line_num))?;
+            } else if name == "audio.volume" {
+                config.beep.volume = value
+                    .parse()
+                    .ok()
+                    .filter(|volume| (0.0..=1.0).contains(volume))
+                    .ok_or(format!("line {}: the volume must be between 0 and 1", line_num))?;
+            } else if name == "audio.waveform" {
+                config.beep.waveform = Waveform::from_name(value)
+                    .ok_or(format!("line {}: unknown waveform `{}`", line_num, value))?;
+            } else {
+                return Err(format!("line {}: unknown setting `{}`", line_num, name));
+            }
+        }
+
+        for (hex_key, keycode) in remaps {
+            config.keymap.remap(hex_key, keycode);
+        }
+
+        Ok(config)
+    }
+}
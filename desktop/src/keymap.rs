@@ -0,0 +1,84 @@
+use chip8_core::emulator::NUM_KEYS;
+use sdl2::keyboard::Keycode;
+
+/// Built-in keyboard layouts. Except for numpad, they all put the
+/// hex keypad on the same physical keys, the 4x4 block under 1234:
+///
+///     1 2 3 C
+///     4 5 6 D
+///     7 8 9 E
+///     A 0 B F
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Qwerty,
+    Azerty,
+    Dvorak,
+    /// Every digit on its own numpad key, A-F on the operators
+    Numpad,
+}
+
+impl Layout {
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name.to_lowercase().as_str() {
+            "qwerty" => Some(Layout::Qwerty),
+            "azerty" => Some(Layout::Azerty),
+            "dvorak" => Some(Layout::Dvorak),
+            "numpad" => Some(Layout::Numpad),
+            _ => None,
+        }
+    }
+
+    /// The keycode of every hex key, from 0 to F
+    fn keys(&self) -> [Keycode; NUM_KEYS] {
+        use Keycode::*;
+
+        match self {
+            Layout::Qwerty => [
+                X, Num1, Num2, Num3, Q, W, E, A,
+                S, D, Z, C, Num4, R, F, V,
+            ],
+            Layout::Azerty => [
+                X, Num1, Num2, Num3, A, Z, E, Q,
+                S, D, W, C, Num4, R, F, V,
+            ],
+            Layout::Dvorak => [
+                Q, Num1, Num2, Num3, Quote, Comma, Period, A,
+                O, E, Semicolon, J, Num4, P, U, K,
+            ],
+            Layout::Numpad => [
+                Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7,
+                Kp8, Kp9, KpDivide, KpMultiply, KpMinus, KpPlus, KpEnter, KpPeriod,
+            ],
+        }
+    }
+}
+
+/// Which keyboard key presses each of the hex keypad keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [Keycode; NUM_KEYS],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new(Layout::Qwerty)
+    }
+}
+
+impl Keymap {
+
+    pub fn new(layout: Layout) -> Self {
+        Self { keys: layout.keys() }
+    }
+
+    /// Binds the hex key to another keyboard key
+    pub fn remap(&mut self, hex_key: usize, keycode: Keycode) {
+        self.keys[hex_key] = keycode;
+    }
+
+    /// The hex key bound to the keyboard key, if any
+    pub fn hex_key(&self, keycode: Keycode) -> Option<usize> {
+        self.keys.iter().position(|&key| key == keycode)
+    }
+}
@@ -1,18 +1,132 @@
+mod audio;
+mod config;
+mod keymap;
+mod rom;
+mod slots;
+
 use chip8_core::*;
+use chip8_core::emulator::Emulator;
+use chip8_core::quirks::Quirks;
+use chip8_core::trace::{TraceFilter, TraceSink, Tracer};
+use audio::Beeper;
+use config::Config;
+use rom::SourceWatcher;
+use slots::SaveSlots;
 use std::env;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
 
 const SCALE: u32 = 15;
 const WINDOW_WIDTH: u32 = (emulator::SCREEN_WIDTH as u32) * SCALE;
 const WINDOW_HEIGHT: u32 = (emulator::SCREEN_HEIGHT as u32) * SCALE;
 
+const TICKS_PER_FRAME: usize = 10;
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// A snapshot every frame, for the last 10 seconds
+const REWIND_CAPACITY: usize = 600;
+const REWIND_INTERVAL: usize = 1;
+
+/// Colors for each pixel value, one bit per XO-CHIP plane
+const PALETTE: [Color; 4] = [
+    Color::RGB(0, 0, 0),
+    Color::RGB(255, 255, 255),
+    Color::RGB(170, 170, 170),
+    Color::RGB(85, 85, 85),
+];
+
 fn main() {
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: cargo run path/to/game");
+    let mut args: Vec<_> = env::args().collect();
+
+    // --config path/to/file can go anywhere
+    let config = match take_option(&mut args, "--config") {
+        Ok(None) => Config::default(),
+        Ok(Some(path)) => match Config::load(&path) {
+            Ok(config) => config,
+            Err(err) => {
+                println!("Couldn't load the config: {}", err);
+                return;
+            }
+        },
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    // --trace path/to/file logs every instruction, - logs them to stderr
+    let tracer = match take_option(&mut args, "--trace") {
+        Ok(None) => None,
+        Ok(Some(path)) if path == "-" => Some(Tracer::new(TraceSink::stderr(), TraceFilter::default())),
+        Ok(Some(path)) => match TraceSink::file(Path::new(&path)) {
+            Ok(sink) => Some(Tracer::new(sink, TraceFilter::default())),
+            Err(err) => {
+                println!("Couldn't create {}: {}", path, err);
+                return;
+            }
+        },
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    // --quirks vip|chip48|schip|xochip picks the compatibility profile
+    let quirks = match take_option(&mut args, "--quirks") {
+        Ok(None) => Quirks::default(),
+        Ok(Some(name)) => match Quirks::from_name(&name) {
+            Some(quirks) => quirks,
+            None => {
+                println!("Unknown quirks profile {}, expected vip, chip48, schip or xochip", name);
+                return;
+            }
+        },
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    if args.len() < 2 || args.len() > 3 {
+        println!("Usage: cargo run path/to/game [ticks_per_frame] [--config path/to/config] [--trace path/to/log] \
+                  [--quirks vip|chip48|schip|xochip]");
+        return;
+    }
+
+    let ticks_per_frame: usize = match args.get(2).map(|arg| arg.parse::<usize>()) {
+        None => TICKS_PER_FRAME,
+        Some(Ok(ticks)) if ticks > 0 => ticks,
+        Some(_) => {
+            println!("ticks_per_frame must be a positive number, got {}", args[2]);
+            return;
+        }
+    };
+
+    let rom = match rom::read(&args[1]) {
+        Ok(rom) => rom,
+        Err(err) => {
+            println!("{}", err);
+            return;
+        }
+    };
+
+    let mut chip8 = Emulator::new(quirks);
+    if let Err(err) = chip8.load(&rom) {
+        println!("Couldn't load {}: {}", args[1], err);
         return;
-    }    
+    }
+    chip8.enable_rewind(REWIND_CAPACITY, REWIND_INTERVAL);
+    if let Some(tracer) = tracer {
+        chip8.enable_trace(tracer);
+    }
 
     // Setup SDL
     let sdl_context = sdl2::init().unwrap();
@@ -26,21 +140,134 @@ fn main() {
 
     let mut canvas = window
         .into_canvas()
-        .present_vsync()
         .build()
         .unwrap();
 
     canvas.clear();
     canvas.present();
 
+    let audio_subsystem = sdl_context.audio().unwrap();
+    let desired_spec = AudioSpecDesired {
+        freq: Some(44100),
+        channels: Some(1),
+        samples: None,
+    };
+    let mut beeper = audio_subsystem
+        .open_playback(None, &desired_spec, |spec| Beeper::new(config.beep, spec.freq))
+        .unwrap();
+    beeper.resume();
+
     let mut event_pump = sdl_context.event_pump().unwrap();
-    
+    let mut running: bool = true;
+    let mut save_slots = SaveSlots::new(&args[1]);
+    let mut rewinding: bool = false;
+    let mut source_watcher = SourceWatcher::new(&args[1]);
+    let mut next_frame = Instant::now();
+
     'gameloop: loop {
         for evt in event_pump.poll_iter() {
             match evt {
                 Event::Quit{..} => break 'gameloop,
+                // F1-F4 pick the save slot, F5 quick-saves and F9 quick-loads
+                Event::KeyDown{keycode: Some(Keycode::F1), ..} => save_slots.select(1),
+                Event::KeyDown{keycode: Some(Keycode::F2), ..} => save_slots.select(2),
+                Event::KeyDown{keycode: Some(Keycode::F3), ..} => save_slots.select(3),
+                Event::KeyDown{keycode: Some(Keycode::F4), ..} => save_slots.select(4),
+                Event::KeyDown{keycode: Some(Keycode::F5), ..} => save_slots.save(&chip8),
+                Event::KeyDown{keycode: Some(Keycode::F9), ..} => running |= save_slots.load(&mut chip8),
+                // Rewind while Backspace is held
+                Event::KeyDown{keycode: Some(Keycode::Backspace), ..} => rewinding = true,
+                Event::KeyUp{keycode: Some(Keycode::Backspace), ..} => rewinding = false,
+                Event::KeyDown{keycode: Some(key), ..} => {
+                    if let Some(k) = config.keymap.hex_key(key) {
+                        chip8.keypress(k, true);
+                    }
+                },
+                Event::KeyUp{keycode: Some(key), ..} => {
+                    if let Some(k) = config.keymap.hex_key(key) {
+                        chip8.keypress(k, false);
+                    }
+                },
                 _ => ()
             }
         }
+
+        // Octo sources are recompiled and restarted when saved.
+        // If they don't compile, the old version keeps running.
+        if source_watcher.as_mut().is_some_and(|watcher| watcher.changed()) {
+            match rom::read(&args[1]) {
+                Ok(rom) => {
+                    chip8.reset();
+                    running = match chip8.load(&rom) {
+                        Ok(()) => {
+                            println!("Reloaded {}", args[1]);
+                            true
+                        }
+                        Err(err) => {
+                            println!("Couldn't load {}: {}", args[1], err);
+                            false
+                        }
+                    };
+                }
+                Err(err) => println!("{}", err),
+            }
+        }
+
+        // Stop emulating on errors, but keep the last frame on screen.
+        // Rewinding back from the error resumes the emulation.
+        if rewinding {
+            running |= chip8.rewind(1);
+        } else if running {
+            if let Err(err) = chip8.run_frame(ticks_per_frame) {
+                println!("Emulation stopped: {}", err);
+                running = false;
+            }
+        }
+        draw_screen(&chip8, &mut canvas);
+        beeper.lock().active = running && !rewinding && chip8.is_sound_active();
+
+        // Keep the frames at 60 Hz
+        next_frame += FRAME_DURATION;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
     }
 }
+
+/// Removes `name value` from the arguments, wherever it is, and returns the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == name) {
+        None => Ok(None),
+        Some(idx) if idx + 1 < args.len() => {
+            let value: String = args.remove(idx + 1);
+            args.remove(idx);
+            Ok(Some(value))
+        }
+        Some(_) => Err(format!("{} needs a value after it", name)),
+    }
+}
+
+/// Paints every pixel with its palette color. The logical size
+/// matches the current resolution, and SDL scales it to the window.
+fn draw_screen(emu: &Emulator, canvas: &mut Canvas<Window>) {
+    let screen = emu.get_display();
+    let width: usize = screen.width();
+    canvas.set_logical_size(width as u32, screen.height() as u32).unwrap();
+
+    canvas.set_draw_color(PALETTE[0]);
+    canvas.clear();
+
+    for (idx, &pixel) in screen.pixels().iter().enumerate() {
+        if pixel != 0 {
+            let x: i32 = (idx % width) as i32;
+            let y: i32 = (idx / width) as i32;
+            canvas.set_draw_color(PALETTE[pixel as usize % PALETTE.len()]);
+            canvas.draw_point((x, y)).unwrap();
+        }
+    }
+
+    canvas.present();
+}
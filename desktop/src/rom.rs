@@ -0,0 +1,62 @@
+use chip8_core::octo;
+use std::fs;
+use std::time::SystemTime;
+
+/// Frames between checks for changes to the source file, about half a second
+const CHECK_INTERVAL: usize = 30;
+
+fn is_octo_source(path: &str) -> bool {
+    path.ends_with(".8o")
+}
+
+/// Reads a ROM, compiling it first when it's Octo source (a .8o file)
+pub fn read(path: &str) -> Result<Vec<u8>, String> {
+    if !is_octo_source(path) {
+        return fs::read(path).map_err(|err| format!("Couldn't read {}: {}", path, err));
+    }
+
+    let source = fs::read_to_string(path).map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+    octo::compile(&source).map_err(|err| format!("{}: {}", path, err))
+}
+
+/// Notices when an Octo source file is saved, so it can be recompiled
+pub struct SourceWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+    frames: usize,
+}
+
+impl SourceWatcher {
+
+    /// None unless the path is Octo source
+    pub fn new(path: &str) -> Option<Self> {
+        if !is_octo_source(path) {
+            return None;
+        }
+        Some(Self {
+            path: path.to_string(),
+            modified: modified_time(path),
+            frames: 0,
+        })
+    }
+
+    /// Call once per frame. Returns true when the file changed since the last time.
+    pub fn changed(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames < CHECK_INTERVAL {
+            return false;
+        }
+        self.frames = 0;
+
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
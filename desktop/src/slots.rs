@@ -0,0 +1,58 @@
+use chip8_core::emulator::Emulator;
+use std::fs;
+
+pub const NUM_SLOTS: usize = 4;
+
+/// Quick-save slots, each one a file next to the ROM: game.ch8.state1 to game.ch8.state4.
+/// Being plain files, they can be attached to bug reports and loaded on another machine.
+pub struct SaveSlots {
+    rom_path: String,
+    selected: usize,
+}
+
+impl SaveSlots {
+
+    pub fn new(rom_path: &str) -> Self {
+        Self {
+            rom_path: rom_path.to_string(),
+            selected: 1,
+        }
+    }
+
+    /// Slots go from 1 to NUM_SLOTS
+    pub fn select(&mut self, slot: usize) {
+        self.selected = slot.clamp(1, NUM_SLOTS);
+        println!("Save slot {} selected", self.selected);
+    }
+
+    fn path(&self) -> String {
+        format!("{}.state{}", self.rom_path, self.selected)
+    }
+
+    pub fn save(&self, emu: &Emulator) {
+        let path = self.path();
+        match fs::write(&path, emu.save_state()) {
+            Ok(()) => println!("Saved state to {}", path),
+            Err(err) => println!("Couldn't save state to {}: {}", path, err),
+        }
+    }
+
+    /// Returns true if the emulator state was replaced
+    pub fn load(&self, emu: &mut Emulator) -> bool {
+        let path = self.path();
+        let result = fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| emu.load_state(&state).map_err(|err| err.to_string()));
+
+        match result {
+            Ok(()) => {
+                println!("Loaded state from {}", path);
+                true
+            }
+            Err(err) => {
+                println!("Couldn't load state from {}: {}", path, err);
+                false
+            }
+        }
+    }
+}